@@ -64,6 +64,14 @@ class MainActivity : TauriActivity() {
         return res
     }
 
+    /** Detached file descriptor for reading a file in place, or -1 */
+    fun safOpenFd(relPath: String): Int {
+        Log.d("SafKit", "MainActivity.safOpenFd(relPath='${'$'}relPath')")
+        val fd = safKit.openFd(relPath)
+        Log.d("SafKit", "MainActivity.safOpenFd -> ${'$'}fd")
+        return fd
+    }
+
     fun safRemove(relPath: String, recursive: Boolean): Boolean {
         Log.d("SafKit", "MainActivity.safRemove(relPath='${'$'}relPath', recursive=${'$'}recursive)")
         val ok = safKit.removePath(relPath, recursive)
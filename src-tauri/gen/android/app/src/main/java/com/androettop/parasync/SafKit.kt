@@ -294,6 +294,28 @@ class SafKit(private val activity: Activity) {
         return null
     }
 
+    /**
+     * Opens a file for reading and hands its descriptor over to the caller (Rust closes it).
+     * Returns -1 if the file can't be opened.
+     */
+    fun openFd(relPath: String): Int {
+        Log.d("SafKit", "openFd(): relPath='$relPath'")
+        val file = resolve(relPath) ?: run {
+            Log.w("SafKit", "openFd(): resolve returned null for '$relPath'")
+            return -1
+        }
+        if (!file.isFile) {
+            Log.w("SafKit", "openFd(): target is not a file uri=${file.uri}")
+            return -1
+        }
+        return try {
+            activity.contentResolver.openFileDescriptor(file.uri, "r")?.detachFd() ?: -1
+        } catch (e: Exception) {
+            Log.w("SafKit", "openFd(): open failed uri=${file.uri}: " + e.message)
+            -1
+        }
+    }
+
     fun removePath(relPath: String, recursive: Boolean): Boolean {
         Log.d("SafKit", "removePath(): relPath='$relPath' recursive=${'$'}recursive")
         val target = resolve(relPath) ?: run {
@@ -0,0 +1,17 @@
+use crate::song_archive::{self, ArchiveEntry};
+
+/// Lists the entries of a song archive (a local path or `saf://<path>` on Android)
+#[tauri::command]
+pub async fn list_song_archive(archive_path: String) -> Result<Vec<ArchiveEntry>, String> {
+    tauri::async_runtime::spawn_blocking(move || song_archive::list_entries(&archive_path))
+        .await
+        .map_err(|e| format!("Join error: {e}"))?
+}
+
+/// Reads one file (chart, cover...) from a song archive without extracting it
+#[tauri::command]
+pub async fn read_song_archive_file(archive_path: String, entry: String) -> Result<Vec<u8>, String> {
+    tauri::async_runtime::spawn_blocking(move || song_archive::read_entry(&archive_path, &entry))
+        .await
+        .map_err(|e| format!("Join error: {e}"))?
+}
@@ -0,0 +1,520 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+/// Archive formats song packs are published in, told apart by their first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    TarZst,
+    SevenZ,
+}
+
+impl ArchiveFormat {
+    pub fn detect(path: &Path) -> Result<Self, String> {
+        let mut magic = [0u8; 6];
+        let mut file = File::open(path).map_err(|e| format!("Could not open {}: {e}", path.display()))?;
+        let n = read_up_to(&mut file, &mut magic).map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        match &magic[..n] {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Ok(Self::Zip),
+            [0x1f, 0x8b, ..] => Ok(Self::TarGz),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(Self::TarZst),
+            [b'7', b'z', 0xbc, 0xaf, 0x27, 0x1c] => Ok(Self::SevenZ),
+            _ => Err("Unsupported archive: expected ZIP, tar.gz, tar.zst or 7z".into()),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zip => "ZIP",
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+            Self::SevenZ => "7z",
+        }
+    }
+}
+
+/// One entry of an archive; `path` is already checked to stay inside the extraction folder
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,               // uncompressed size declared by the archive
+    pub compressed: Option<u64>, // known for ZIP entries only
+}
+
+/// Safeguards against archives that would exhaust the disk (zip bombs, corrupt headers).
+/// Checked on the declared sizes before extracting, then on the bytes actually written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractLimits {
+    pub max_entries: u64,
+    pub max_total_bytes: u64, // uncompressed size of the whole archive
+    pub max_entry_bytes: u64,
+    pub max_ratio: u64,       // uncompressed / compressed size, per ZIP entry and for the whole archive
+    pub min_free_bytes: u64,  // disk space that must stay free after extracting
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        Self {
+            max_entries: 10_000,
+            max_total_bytes: 8 * GIB,
+            max_entry_bytes: 4 * GIB,
+            max_ratio: 200,
+            min_free_bytes: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// What `extract` reports while it runs
+pub enum ExtractStep<'a> {
+    Started { entries: u64, bytes: u64 },
+    Entry(&'a str),
+    Bytes(u64),
+    EntryDone,
+}
+
+pub fn list_entries(archive: &Path, format: ArchiveFormat) -> Result<Vec<EntryInfo>, String> {
+    let mut entries = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = open_zip(archive)?;
+            for i in 0..zip.len() {
+                let entry = zip.by_index_raw(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
+                let path = entry.enclosed_name().ok_or_else(|| format!("Unsafe path in ZIP entry #{i}"))?.to_owned();
+                entries.push(EntryInfo {
+                    path,
+                    is_dir: entry.is_dir(),
+                    size: entry.size(),
+                    compressed: Some(entry.compressed_size()),
+                });
+            }
+        }
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            let mut tar = open_tar(archive, format)?;
+            for entry in tar.entries().map_err(|e| format!("Invalid tar: {e}"))? {
+                let entry = entry.map_err(|e| format!("Invalid tar entry: {e}"))?;
+                let Some((path, is_dir)) = tar_entry_path(&entry)? else { continue };
+                entries.push(EntryInfo { path, is_dir, size: entry.size(), compressed: None });
+            }
+        }
+        ArchiveFormat::SevenZ => {
+            let reader = open_7z(archive)?;
+            for entry in &reader.archive().files {
+                let Some(path) = entry_path(&entry.name, "7z")? else { continue };
+                entries.push(EntryInfo { path, is_dir: entry.is_directory, size: entry.size, compressed: None });
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Reads one file (e.g. a chart) out of the archive. Tar and 7z are scanned up to the entry.
+pub fn read_file(archive: &Path, format: ArchiveFormat, wanted: &Path) -> Result<Vec<u8>, String> {
+    let not_found = || format!("{} not found in {}", wanted.display(), archive.display());
+    let mut data = Vec::new();
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = open_zip(archive)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
+                if entry.enclosed_name() == Some(wanted) {
+                    entry.read_to_end(&mut data).map_err(|e| format!("Could not read {}: {e}", wanted.display()))?;
+                    return Ok(data);
+                }
+            }
+            Err(not_found())
+        }
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            let mut tar = open_tar(archive, format)?;
+            for entry in tar.entries().map_err(|e| format!("Invalid tar: {e}"))? {
+                let mut entry = entry.map_err(|e| format!("Invalid tar entry: {e}"))?;
+                if matches!(tar_entry_path(&entry)?, Some((p, false)) if p == wanted) {
+                    entry.read_to_end(&mut data).map_err(|e| format!("Could not read {}: {e}", wanted.display()))?;
+                    return Ok(data);
+                }
+            }
+            Err(not_found())
+        }
+        ArchiveFormat::SevenZ => {
+            let mut found = false;
+            open_7z(archive)?
+                .for_each_entries(|entry, reader| {
+                    if !matches!(entry_path(&entry.name, "7z"), Ok(Some(p)) if p == wanted) {
+                        io::copy(reader, &mut io::sink())?; // solid blocks decode in order
+                        return Ok(true);
+                    }
+                    reader.read_to_end(&mut data)?;
+                    found = true;
+                    Ok(false)
+                })
+                .map_err(|e| format!("Invalid 7z: {e}"))?;
+            if found { Ok(data) } else { Err(not_found()) }
+        }
+    }
+}
+
+/// Extracts the whole archive under `dest_root`, mirroring its structure. Paths leaving
+/// `dest_root` are refused (zip-slip); links and special files in tar archives are skipped.
+/// Stops at the first exceeded limit; the caller removes what was written.
+pub fn extract(
+    archive: &Path,
+    format: ArchiveFormat,
+    dest_root: &Path,
+    limits: &ExtractLimits,
+    report: &mut dyn FnMut(ExtractStep),
+) -> Result<(), String> {
+    let listed = list_entries(archive, format)?;
+    let archive_size = fs::metadata(archive).map_err(|e| format!("Could not read {}: {e}", archive.display()))?.len();
+    let mut budget = Budget::new(limits, archive_size, dest_root);
+    budget.check_declared(&listed)?;
+    report(ExtractStep::Started {
+        entries: listed.len() as u64,
+        bytes: listed.iter().map(|e| e.size).sum(),
+    });
+    let mut buf = vec![0u8; 256 * 1024];
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = open_zip(archive)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
+                let relpath = entry.enclosed_name().ok_or_else(|| format!("Unsafe ZIP path in entry #{i}"))?.to_owned();
+                let name = entry.name().to_string();
+                report(ExtractStep::Entry(&name));
+                budget.start_entry()?;
+                let out_path = dest_root.join(relpath);
+                if entry.is_dir() {
+                    create_dir(&out_path)?;
+                } else {
+                    let compressed = Some(entry.compressed_size());
+                    write_entry(&mut entry, &out_path, compressed, &mut buf, &mut budget, report)?;
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        if let Some(mode) = entry.unix_mode() {
+                            let _ = fs::set_permissions(&out_path, fs::Permissions::from_mode(mode));
+                        }
+                    }
+                }
+                report(ExtractStep::EntryDone);
+            }
+        }
+        ArchiveFormat::TarGz | ArchiveFormat::TarZst => {
+            let mut tar = open_tar(archive, format)?;
+            for entry in tar.entries().map_err(|e| format!("Invalid tar: {e}"))? {
+                let mut entry = entry.map_err(|e| format!("Invalid tar entry: {e}"))?;
+                let Some((relpath, is_dir)) = tar_entry_path(&entry)? else { continue };
+                let name = relpath.to_string_lossy().to_string();
+                report(ExtractStep::Entry(&name));
+                budget.start_entry()?;
+                let out_path = dest_root.join(relpath);
+                if is_dir {
+                    create_dir(&out_path)?;
+                } else {
+                    write_entry(&mut entry, &out_path, None, &mut buf, &mut budget, report)?;
+                }
+                report(ExtractStep::EntryDone);
+            }
+        }
+        ArchiveFormat::SevenZ => {
+            let mut failure = None;
+            open_7z(archive)?
+                .for_each_entries(|entry, reader| {
+                    let relpath = match entry_path(&entry.name, "7z") {
+                        Ok(Some(p)) => p,
+                        Ok(None) => return Ok(true), // the archive root itself
+                        Err(e) => {
+                            failure = Some(e);
+                            return Ok(false);
+                        }
+                    };
+                    report(ExtractStep::Entry(&entry.name));
+                    let out_path = dest_root.join(relpath);
+                    let result = budget.start_entry().and_then(|_| {
+                        if entry.is_directory {
+                            create_dir(&out_path)
+                        } else {
+                            write_entry(reader, &out_path, None, &mut buf, &mut budget, report)
+                        }
+                    });
+                    if let Err(e) = result {
+                        failure = Some(e);
+                        return Ok(false);
+                    }
+                    report(ExtractStep::EntryDone);
+                    Ok(true)
+                })
+                .map_err(|e| format!("Invalid 7z: {e}"))?;
+            if let Some(e) = failure { return Err(e); }
+        }
+    }
+    Ok(())
+}
+
+/// Relative path made of plain components only. Paths with a root, drive or `..` are refused;
+/// `None` is the archive root itself ("", "./").
+fn entry_path(name: &str, format: &str) -> Result<Option<PathBuf>, String> {
+    let normalized = name.replace('\\', "/");
+    let mut out = PathBuf::new();
+    for component in Path::new(&normalized).components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(format!("Unsafe path in {format} entry {name}"));
+            }
+        }
+    }
+    Ok((!out.as_os_str().is_empty()).then_some(out))
+}
+
+/// Path and directory flag of files and folders; `None` for links and special entries
+fn tar_entry_path<R: Read>(entry: &tar::Entry<R>) -> Result<Option<(PathBuf, bool)>, String> {
+    let is_dir = match entry.header().entry_type() {
+        tar::EntryType::Directory => true,
+        tar::EntryType::Regular | tar::EntryType::Continuous => false,
+        _ => return Ok(None),
+    };
+    let raw = entry.path().map_err(|e| format!("Invalid tar entry path: {e}"))?;
+    Ok(entry_path(&raw.to_string_lossy(), "tar")?.map(|path| (path, is_dir)))
+}
+
+fn write_entry(
+    reader: &mut dyn Read,
+    out_path: &Path,
+    compressed: Option<u64>,
+    buf: &mut [u8],
+    budget: &mut Budget,
+    report: &mut dyn FnMut(ExtractStep),
+) -> Result<(), String> {
+    if let Some(parent) = out_path.parent() {
+        create_dir(parent)?;
+    }
+    let mut outfile = File::create(out_path)
+        .map_err(|e| format!("Could not create file {}: {e}", out_path.display()))?;
+    let mut written = 0u64;
+    loop {
+        let n = reader.read(buf).map_err(|e| format!("Error reading {}: {e}", out_path.display()))?;
+        if n == 0 { break; }
+        written += n as u64;
+        // checked before writing, so a bomb never lands on disk
+        budget.add_bytes(out_path, written, compressed, n as u64)?;
+        outfile.write_all(&buf[..n])
+            .map_err(|e| format!("Error writing {}: {e}", out_path.display()))?;
+        report(ExtractStep::Bytes(n as u64));
+    }
+    Ok(())
+}
+
+/// What an extraction may still use, from `ExtractLimits`
+struct Budget<'a> {
+    limits: &'a ExtractLimits,
+    archive_size: u64,
+    dest_root: &'a Path,
+    entries: u64,
+    written: u64,
+    unchecked_space: u64, // bytes written since free space was last checked
+}
+
+/// Free space is queried again after this many bytes
+const SPACE_CHECK_INTERVAL: u64 = 64 * 1024 * 1024;
+
+impl<'a> Budget<'a> {
+    fn new(limits: &'a ExtractLimits, archive_size: u64, dest_root: &'a Path) -> Self {
+        Self { limits, archive_size, dest_root, entries: 0, written: 0, unchecked_space: 0 }
+    }
+
+    /// Refuses archives whose own headers already exceed a limit
+    fn check_declared(&self, entries: &[EntryInfo]) -> Result<(), String> {
+        let l = self.limits;
+        if entries.len() as u64 > l.max_entries {
+            return Err(format!("Archive has {} entries, the limit is {}", entries.len(), l.max_entries));
+        }
+        let mut total = 0u64;
+        for e in entries.iter().filter(|e| !e.is_dir) {
+            if e.size > l.max_entry_bytes {
+                return Err(format!("{} would be {} bytes, the limit per file is {}", e.path.display(), e.size, l.max_entry_bytes));
+            }
+            if let Some(c) = e.compressed {
+                check_ratio(&e.path.display().to_string(), e.size, c, l.max_ratio)?;
+            }
+            total = total.saturating_add(e.size);
+        }
+        if total > l.max_total_bytes {
+            return Err(format!("Archive would extract to {total} bytes, the limit is {}", l.max_total_bytes));
+        }
+        check_ratio("The archive", total, self.archive_size, l.max_ratio)?;
+        self.check_space(total)
+    }
+
+    fn start_entry(&mut self) -> Result<(), String> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(format!("Archive has more than {} entries", self.limits.max_entries));
+        }
+        Ok(())
+    }
+
+    /// Accounts `n` more bytes of an entry that has `entry_written` bytes so far
+    fn add_bytes(&mut self, path: &Path, entry_written: u64, compressed: Option<u64>, n: u64) -> Result<(), String> {
+        let l = self.limits;
+        self.written += n;
+        if entry_written > l.max_entry_bytes {
+            return Err(format!("{} is larger than the limit of {} bytes per file", path.display(), l.max_entry_bytes));
+        }
+        if self.written > l.max_total_bytes {
+            return Err(format!("Archive extracts to more than the limit of {} bytes", l.max_total_bytes));
+        }
+        if let Some(c) = compressed {
+            check_ratio(&path.display().to_string(), entry_written, c, l.max_ratio)?;
+        }
+        check_ratio("The archive", self.written, self.archive_size, l.max_ratio)?;
+        self.unchecked_space += n;
+        if self.unchecked_space >= SPACE_CHECK_INTERVAL {
+            self.unchecked_space = 0;
+            self.check_space(0)?;
+        }
+        Ok(())
+    }
+
+    /// Fails if writing `more` bytes would leave less than `min_free_bytes` free
+    fn check_space(&self, more: u64) -> Result<(), String> {
+        // the extraction folder may not exist yet: ask its closest existing parent
+        let Some(dir) = self.dest_root.ancestors().find(|p| p.exists()) else { return Ok(()) };
+        let Ok(free) = fs4::available_space(dir) else { return Ok(()) };
+        if free < more.saturating_add(self.limits.min_free_bytes) {
+            return Err(format!(
+                "Not enough disk space: {free} bytes free, {more} needed plus {} to keep free",
+                self.limits.min_free_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Below this size a high compression ratio is harmless (padding, repetitive charts...)
+const RATIO_MIN_BYTES: u64 = 1024 * 1024;
+
+fn check_ratio(what: &str, uncompressed: u64, compressed: u64, max_ratio: u64) -> Result<(), String> {
+    if uncompressed > RATIO_MIN_BYTES && uncompressed > compressed.max(1).saturating_mul(max_ratio) {
+        return Err(format!(
+            "{what} expands more than {max_ratio}x ({compressed} -> {uncompressed} bytes), refusing a possible zip bomb"
+        ));
+    }
+    Ok(())
+}
+
+fn create_dir(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path).map_err(|e| format!("Could not create dir {}: {e}", path.display()))
+}
+
+fn open_zip(archive: &Path) -> Result<zip::ZipArchive<File>, String> {
+    let file = File::open(archive).map_err(|e| format!("Could not open ZIP {}: {e}", archive.display()))?;
+    zip::ZipArchive::new(file).map_err(|e| format!("Invalid ZIP: {e}"))
+}
+
+fn open_tar(archive: &Path, format: ArchiveFormat) -> Result<tar::Archive<Box<dyn Read>>, String> {
+    let file = BufReader::new(
+        File::open(archive).map_err(|e| format!("Could not open {}: {e}", archive.display()))?,
+    );
+    let decoder: Box<dyn Read> = match format {
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        _ => Box::new(zstd::stream::read::Decoder::with_buffer(file).map_err(|e| format!("Invalid zstd stream: {e}"))?),
+    };
+    Ok(tar::Archive::new(decoder))
+}
+
+fn open_7z(archive: &Path) -> Result<sevenz_rust::SevenZReader<File>, String> {
+    sevenz_rust::SevenZReader::open(archive, sevenz_rust::Password::empty())
+        .map_err(|e| format!("Invalid 7z {}: {e}", archive.display()))
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match file.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn limits() -> ExtractLimits {
+        ExtractLimits {
+            max_entries: 3,
+            max_total_bytes: 10 * MIB,
+            max_entry_bytes: 4 * MIB,
+            max_ratio: 10,
+            min_free_bytes: 0,
+        }
+    }
+
+    fn file(name: &str, size: u64, compressed: Option<u64>) -> EntryInfo {
+        EntryInfo { path: PathBuf::from(name), is_dir: false, size, compressed }
+    }
+
+    #[test]
+    fn ratio_is_only_checked_above_the_minimum_size() {
+        assert!(check_ratio("x", RATIO_MIN_BYTES, 1, 10).is_ok());
+        assert!(check_ratio("x", RATIO_MIN_BYTES + 1, 1, 10).is_err());
+        // a zero compressed size counts as one byte
+        assert!(check_ratio("x", RATIO_MIN_BYTES + 1, 0, 10).is_err());
+    }
+
+    #[test]
+    fn ratio_threshold_is_inclusive() {
+        assert!(check_ratio("x", 20 * MIB, 2 * MIB, 10).is_ok());
+        assert!(check_ratio("x", 20 * MIB + 1, 2 * MIB, 10).is_err());
+        assert!(check_ratio("x", u64::MAX, u64::MAX, 10).is_ok());
+    }
+
+    #[test]
+    fn declared_sizes_within_limits() {
+        let (limits, dest) = (limits(), std::env::temp_dir());
+        let budget = Budget::new(&limits, 2 * MIB, &dest);
+        let entries = [
+            file("Song/a.ogg", 4 * MIB, Some(MIB)),
+            EntryInfo { path: PathBuf::from("Song"), is_dir: true, size: 0, compressed: None },
+            file("Song/a.rlrr", 100, Some(0)),
+        ];
+        assert!(budget.check_declared(&entries).is_ok());
+    }
+
+    #[test]
+    fn declared_sizes_over_a_limit() {
+        let (limits, dest) = (limits(), std::env::temp_dir());
+        let budget = Budget::new(&limits, 10 * MIB, &dest);
+        let many: Vec<EntryInfo> = (0..4).map(|i| file(&format!("{i}.txt"), 1, None)).collect();
+        assert!(budget.check_declared(&many).unwrap_err().contains("4 entries"));
+        let big = [file("big.ogg", 4 * MIB + 1, None)];
+        assert!(budget.check_declared(&big).unwrap_err().contains("limit per file"));
+        let total = [file("a.ogg", 4 * MIB, None), file("b.ogg", 4 * MIB, None), file("c.ogg", 3 * MIB, None)];
+        assert!(budget.check_declared(&total).unwrap_err().contains("would extract to"));
+    }
+
+    #[test]
+    fn declared_ratio_per_entry_and_for_the_archive() {
+        let (limits, dest) = (limits(), std::env::temp_dir());
+        let budget = Budget::new(&limits, 10 * MIB, &dest);
+        let bomb = [file("bomb.bin", 4 * MIB, Some(100))];
+        assert!(budget.check_declared(&bomb).unwrap_err().contains("bomb.bin expands"));
+
+        // entries of unknown compressed size (tar, 7z) are only checked as a whole
+        let budget = Budget::new(&limits, MIB / 2, &dest);
+        let entries = [file("a.ogg", 3 * MIB, None), file("b.ogg", 3 * MIB, None)];
+        assert!(budget.check_declared(&entries).unwrap_err().contains("The archive expands"));
+    }
+}
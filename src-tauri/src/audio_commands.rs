@@ -1,8 +1,11 @@
-use crate::audio_service::{AUDIO, AudioStatus};
+use crate::audio_decode::{self, AudioProbe};
+use crate::audio_service::{AUDIO, AudioMeters, AudioStatus, AutomationPoint, LoopRegion, MixSettings, OutputSettings, QueuedSong};
 
+/// Loads the tracks. With `song_dir`, the mix preset saved in that song folder is recalled.
+/// `duration_secs` lets the song crossfade into the next queued one.
 #[tauri::command]
-pub fn load_audio(paths: Vec<String>) -> Result<(), String> {
-    AUDIO.load(paths)
+pub fn load_audio(paths: Vec<String>, song_dir: Option<String>, duration_secs: Option<f64>) -> Result<(), String> {
+    AUDIO.load(paths, song_dir, duration_secs)
 }
 
 #[tauri::command]
@@ -35,6 +38,18 @@ pub fn audio_status() -> Result<AudioStatus, String> {
     Ok(AUDIO.status())
 }
 
+/// Peak/RMS levels per track and for the master bus. Peaks are held until the next call.
+#[tauri::command]
+pub fn audio_meters() -> Result<AudioMeters, String> {
+    Ok(AUDIO.meters())
+}
+
+/// Reports container/codec for each file, or the structured reason it can't be played
+#[tauri::command]
+pub fn probe_audio(paths: Vec<String>) -> Result<Vec<AudioProbe>, String> {
+    Ok(paths.into_iter().map(audio_decode::probe_file).collect())
+}
+
 #[tauri::command]
 pub fn set_tracks_gain_by_path(
     paths: Vec<String>,
@@ -51,4 +66,98 @@ pub fn mute_tracks_by_path(
     ramp_ms: Option<u32>,
 ) -> Result<(), String> {
     AUDIO.mute_tracks_by_path(paths, muted, ramp_ms)
-}
\ No newline at end of file
+}
+
+#[tauri::command]
+pub fn set_tracks_pan_by_path(paths: Vec<String>, pan: f32) -> Result<(), String> {
+    AUDIO.set_tracks_pan_by_path(paths, pan)
+}
+
+/// Shifts tracks against the others: positive `offset_ms` delays them, negative starts them earlier.
+#[tauri::command]
+pub fn set_tracks_offset_by_path(paths: Vec<String>, offset_ms: f32) -> Result<(), String> {
+    AUDIO.set_tracks_offset_by_path(paths, offset_ms)
+}
+
+/// Schedules gain changes over song time for the given tracks, replacing their previous envelope.
+#[tauri::command]
+pub fn set_tracks_automation_by_path(paths: Vec<String>, points: Vec<AutomationPoint>) -> Result<(), String> {
+    AUDIO.set_tracks_automation_by_path(paths, points)
+}
+
+#[tauri::command]
+pub fn clear_tracks_automation_by_path(paths: Vec<String>) -> Result<(), String> {
+    AUDIO.set_tracks_automation_by_path(paths, Vec::new())
+}
+
+#[tauri::command]
+pub fn set_playback_rate(rate: f32) -> Result<(), String> {
+    AUDIO.set_playback_rate(rate)
+}
+
+/// `None` disables looping.
+#[tauri::command]
+pub fn set_loop_region(region: Option<LoopRegion>) -> Result<(), String> {
+    AUDIO.set_loop_region(region)
+}
+
+#[tauri::command]
+pub fn get_mix_settings() -> Result<MixSettings, String> {
+    AUDIO.mix_settings()
+}
+
+#[tauri::command]
+pub fn apply_mix_settings(settings: MixSettings) -> Result<(), String> {
+    AUDIO.apply_mix_settings(settings)
+}
+
+/// Saves the current mix into the song's parasync.json.
+#[tauri::command]
+pub fn save_song_mix(song_dir: String) -> Result<(), String> {
+    AUDIO.save_song_mix(song_dir)
+}
+
+/// Recalls the mix saved in the song's parasync.json. Returns false if there is none.
+#[tauri::command]
+pub fn load_song_mix(song_dir: String) -> Result<bool, String> {
+    AUDIO.load_song_mix(song_dir)
+}
+
+/// Queues a song to play after the current one. Emits `audio-song-changed` when it starts.
+#[tauri::command]
+pub fn queue_song(paths: Vec<String>, song_dir: Option<String>, duration_secs: Option<f64>) -> Result<(), String> {
+    AUDIO.queue_song(QueuedSong { paths, song_dir, duration_secs })
+}
+
+#[tauri::command]
+pub fn clear_song_queue() -> Result<(), String> {
+    AUDIO.clear_song_queue()
+}
+
+#[tauri::command]
+pub fn song_queue() -> Result<Vec<QueuedSong>, String> {
+    AUDIO.song_queue()
+}
+
+/// Crossfade length between queued songs; 0 switches gaplessly.
+#[tauri::command]
+pub fn set_crossfade(ms: u32) -> Result<(), String> {
+    AUDIO.set_crossfade(ms)
+}
+
+/// Device buffer size in frames; omit it to go back to the device default
+#[tauri::command]
+pub fn set_output_buffer_size(frames: Option<u32>) -> Result<OutputSettings, String> {
+    AUDIO.set_output_buffer_size(frames)
+}
+
+/// Mixing block size in frames (clamped to 64..=8192)
+#[tauri::command]
+pub fn set_mix_block_size(frames: usize) -> Result<OutputSettings, String> {
+    AUDIO.set_mix_block_size(frames)
+}
+
+#[tauri::command]
+pub fn output_settings() -> Result<OutputSettings, String> {
+    AUDIO.output_settings()
+}
@@ -0,0 +1,426 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{self, CodecRegistry, CodecType, Decoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymError,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+    probe::Hint,
+    units::Time,
+};
+
+use crate::saf_service::{SAF, SAF_PATH_PREFIX};
+use crate::song_archive::{self, ZipEntryReader};
+
+/// symphonia's enabled codecs, plus the libopus decoder when built with the `opus` feature
+static CODECS: Lazy<CodecRegistry> = Lazy::new(|| {
+    let mut registry = CodecRegistry::new();
+    symphonia::default::register_enabled_codecs(&mut registry);
+    #[cfg(feature = "opus")]
+    registry.register_all::<crate::opus_decoder::OpusDecoder>();
+    registry
+});
+
+// ---------- Errors ----------
+
+/// Why a track could not be decoded. Serialized with a `kind` tag so the frontend can tell
+/// a missing file from an unsupported codec.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DecodeError {
+    Open { path: String, message: String },
+    /// The container was not recognized or can't be read (`container` is what the header looks like)
+    UnsupportedContainer { path: String, container: Option<String>, reason: String },
+    /// The container was read but no decoder handles its audio codec
+    UnsupportedCodec { path: String, container: Option<String>, codec: String, reason: String },
+    NoAudioTrack { path: String, container: Option<String> },
+    Corrupt { path: String, container: Option<String>, message: String },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let in_container = |c: &Option<String>| c.as_ref().map(|c| format!(" ({c})")).unwrap_or_default();
+        match self {
+            DecodeError::Open { path, message } => write!(f, "Could not open {path}: {message}"),
+            DecodeError::UnsupportedContainer { path, container, reason } => match container {
+                Some(c) => write!(f, "Unsupported {c} file {path}: {reason}"),
+                None => write!(f, "Unrecognized audio format in {path}: {reason}"),
+            },
+            DecodeError::UnsupportedCodec { path, container, codec, reason } => {
+                write!(f, "Unsupported codec {codec}{} in {path}: {reason}", in_container(container))
+            }
+            DecodeError::NoAudioTrack { path, container } => {
+                write!(f, "No audio track found in {path}{}", in_container(container))
+            }
+            DecodeError::Corrupt { path, container, message } => {
+                write!(f, "Could not decode {path}{}: {message}", in_container(container))
+            }
+        }
+    }
+}
+
+impl From<DecodeError> for String {
+    fn from(e: DecodeError) -> Self {
+        e.to_string()
+    }
+}
+
+// ---------- Probe ----------
+
+/// What was found in an audio file that decodes fine
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioInfo {
+    pub container: Option<String>,
+    pub codec: String,
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub duration_secs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioProbe {
+    pub path: String,
+    pub info: Option<AudioInfo>,
+    pub error: Option<DecodeError>,
+}
+
+/// Opens the file and decodes its first packet
+pub fn probe_file(path: String) -> AudioProbe {
+    match AudioDecoder::open(&path) {
+        Ok(dec) => AudioProbe { info: Some(dec.info()), error: None, path },
+        Err(e) => AudioProbe { info: None, error: Some(e), path },
+    }
+}
+
+// ---------- Streaming decoder ----------
+
+/// Interleaved f32 samples from any format/codec symphonia supports, with seeking
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    container: Option<String>,
+    codec: CodecType,
+    channels: usize,
+    sample_rate: u32,
+    n_frames: Option<u64>,
+    buf: Option<SampleBuffer<f32>>,
+    buf_pos: usize,
+    buf_len: usize,
+    skip_samples: usize, // samples to drop after an accurate seek
+    at_end: bool,
+}
+
+impl AudioDecoder {
+    /// Opens a track from the storage backend its path points to (see `open_media`)
+    pub fn open(path: &str) -> Result<Self, DecodeError> {
+        let source = open_media(path).map_err(|message| DecodeError::Open { path: path.to_string(), message })?;
+        Self::from_source(source, path)
+    }
+
+    /// Decodes from any seekable source. `path` names the track in errors and hints the format.
+    pub fn from_source(mut source: Box<dyn MediaSource>, path: &str) -> Result<Self, DecodeError> {
+        let open_err = |message: String| DecodeError::Open { path: path.to_string(), message };
+
+        // keep the header to name the container in errors
+        let mut header = [0u8; 64];
+        let header_len = read_header(&mut source, &mut header).map_err(|e| open_err(e.to_string()))?;
+        let header = &header[..header_len];
+        let container = sniff_container(header);
+
+        let mut hint = Hint::new();
+        if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        let mss = MediaSourceStream::new(source, MediaSourceStreamOptions::default());
+        let format_opts = FormatOptions { enable_gapless: true, ..Default::default() };
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_opts, &MetadataOptions::default())
+            .map_err(|e| DecodeError::UnsupportedContainer {
+                path: path.to_string(),
+                container: container.clone(),
+                reason: container_failure_reason(header, &e),
+            })?;
+        let format = probed.format;
+
+        let track = format.tracks().iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| DecodeError::NoAudioTrack { path: path.to_string(), container: container.clone() })?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+
+        let decoder = CODECS
+            .make(&params, &DecoderOptions::default())
+            .map_err(|e| DecodeError::UnsupportedCodec {
+                path: path.to_string(),
+                container: container.clone(),
+                codec: codec_name(params.codec),
+                reason: codec_failure_reason(params.codec, &e),
+            })?;
+
+        let mut me = Self {
+            format,
+            decoder,
+            track_id,
+            container,
+            codec: params.codec,
+            channels: params.channels.map(|c| c.count()).unwrap_or(0),
+            sample_rate: params.sample_rate.unwrap_or(0),
+            n_frames: params.n_frames,
+            buf: None,
+            buf_pos: 0,
+            buf_len: 0,
+            skip_samples: 0,
+            at_end: false,
+        };
+
+        // decode the first packet: some codecs only report the layout once decoding
+        if let Err(message) = me.decode_next() {
+            return Err(DecodeError::Corrupt { path: path.to_string(), container: me.container, message });
+        }
+        if me.channels == 0 || me.sample_rate == 0 {
+            return Err(DecodeError::Corrupt {
+                path: path.to_string(),
+                container: me.container,
+                message: "missing sample rate or channel layout".into(),
+            });
+        }
+        Ok(me)
+    }
+
+    pub fn channels(&self) -> usize { self.channels }
+    pub fn sample_rate(&self) -> u32 { self.sample_rate }
+
+    pub fn info(&self) -> AudioInfo {
+        AudioInfo {
+            container: self.container.clone(),
+            codec: codec_name(self.codec),
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            duration_secs: self.n_frames.map(|n| n as f64 / self.sample_rate.max(1) as f64),
+        }
+    }
+
+    /// Moves to `secs` (frame-accurate). Falls back to decoding through when the format can't seek.
+    pub fn seek(&mut self, secs: f64) {
+        if secs <= 0.0 { return; }
+        let to = SeekTo::Time { time: Time::from(secs), track_id: Some(self.track_id) };
+        match self.format.seek(SeekMode::Accurate, to) {
+            Ok(seeked) => {
+                self.decoder.reset();
+                self.buf_pos = 0;
+                self.buf_len = 0;
+                self.at_end = false;
+                let extra = seeked.required_ts.saturating_sub(seeked.actual_ts) as usize;
+                self.skip_samples = extra * self.channels;
+            }
+            Err(_) => {
+                let frames = (secs * self.sample_rate as f64).floor() as usize;
+                self.skip_samples = frames * self.channels;
+            }
+        }
+    }
+
+    // Decodes packets until one yields samples. Err only for fatal errors before the first packet.
+    fn decode_next(&mut self) -> Result<(), String> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(SymError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    self.at_end = true;
+                    return Ok(());
+                }
+                Err(e) => {
+                    self.at_end = true;
+                    return Err(e.to_string());
+                }
+            };
+            if packet.track_id() != self.track_id { continue; }
+
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    if decoded.frames() == 0 { continue; }
+                    let spec = *decoded.spec();
+                    if self.channels == 0 { self.channels = spec.channels.count(); }
+                    if self.sample_rate == 0 { self.sample_rate = spec.rate; }
+                    let needed = decoded.capacity() as u64;
+                    let buf = match &mut self.buf {
+                        Some(b) if b.capacity() >= needed as usize * spec.channels.count() => b,
+                        _ => self.buf.insert(SampleBuffer::new(needed, spec)),
+                    };
+                    buf.copy_interleaved_ref(decoded);
+                    self.buf_len = buf.len();
+                    self.buf_pos = 0;
+                    return Ok(());
+                }
+                // a corrupt packet: skip it and keep going
+                Err(SymError::DecodeError(_)) => continue,
+                Err(e) => {
+                    self.at_end = true;
+                    return Err(e.to_string());
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for AudioDecoder {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        loop {
+            if self.buf_pos >= self.buf_len {
+                if self.at_end { return None; }
+                let _ = self.decode_next();
+                if self.buf_pos >= self.buf_len { return None; }
+            }
+            let v = self.buf.as_ref().map(|b| b.samples()[self.buf_pos]).unwrap_or(0.0);
+            self.buf_pos += 1;
+            if self.skip_samples > 0 {
+                self.skip_samples -= 1;
+                continue;
+            }
+            return Some(v);
+        }
+    }
+}
+
+/// Opens the bytes of a track:
+/// - `<archive>.zip!/<entry>`: an entry of a song archive, local or under SAF
+/// - `saf://<path>`: a file under the Android SAF songs root, read in place
+/// - anything else: a local file
+pub fn open_media(path: &str) -> Result<Box<dyn MediaSource>, String> {
+    if let Some((archive, entry)) = song_archive::split_archive_path(path) {
+        return Ok(Box::new(song_archive::open_entry(archive, entry)?));
+    }
+    if let Some(rel) = path.strip_prefix(SAF_PATH_PREFIX) {
+        return Ok(Box::new(SAF.open_file(rel.to_string())?));
+    }
+    Ok(Box::new(File::open(path).map_err(|e| e.to_string())?))
+}
+
+impl MediaSource for ZipEntryReader {
+    fn is_seekable(&self) -> bool { true }
+    fn byte_len(&self) -> Option<u64> { Some(self.size()) }
+}
+
+// ---------- Format identification for error messages ----------
+
+fn read_header<R: Read + Seek + ?Sized>(file: &mut R, header: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < header.len() {
+        match file.read(&mut header[n..])? {
+            0 => break,
+            k => n += k,
+        }
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(n)
+}
+
+/// Names the container from its magic bytes, independent of what symphonia supports
+fn sniff_container(h: &[u8]) -> Option<String> {
+    let name = if h.starts_with(b"OggS") {
+        if contains(h, b"OpusHead") { "Ogg Opus" } else if contains(h, b"\x01vorbis") { "Ogg Vorbis" } else { "Ogg" }
+    } else if h.len() >= 12 && &h[0..4] == b"RIFF" && &h[8..12] == b"WAVE" {
+        "WAV"
+    } else if h.len() >= 12 && &h[0..4] == b"RF64" {
+        "WAV (RF64)"
+    } else if h.len() >= 12 && &h[0..4] == b"FORM" && (&h[8..12] == b"AIFF" || &h[8..12] == b"AIFC") {
+        "AIFF"
+    } else if h.len() >= 8 && &h[4..8] == b"ftyp" {
+        "MP4/M4A"
+    } else if h.starts_with(b"fLaC") {
+        "FLAC"
+    } else if h.starts_with(b"caff") {
+        "CAF"
+    } else if h.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        "Matroska/WebM"
+    } else if h.starts_with(b"ID3") {
+        "MP3 (ID3)"
+    } else if h.len() >= 2 && h[0] == 0xFF && (h[1] & 0xF6) == 0xF0 {
+        "AAC (ADTS)"
+    } else if h.len() >= 2 && h[0] == 0xFF && (h[1] & 0xE0) == 0xE0 {
+        "MPEG audio"
+    } else if h.starts_with(b"#!AMR") {
+        "AMR"
+    } else if h.starts_with(b"MAC ") {
+        "Monkey's Audio"
+    } else if h.starts_with(b"wvpk") {
+        "WavPack"
+    } else {
+        return None;
+    };
+    Some(name.to_string())
+}
+
+fn contains(h: &[u8], needle: &[u8]) -> bool {
+    h.windows(needle.len()).any(|w| w == needle)
+}
+
+fn container_failure_reason(header: &[u8], err: &SymError) -> String {
+    if let Some(fmt) = describe_wav_format(header) {
+        return format!("{fmt} ({err})");
+    }
+    match sniff_container(header).as_deref() {
+        Some("AMR") | Some("Monkey's Audio") | Some("WavPack") => "this container is not supported".into(),
+        Some(_) => err.to_string(),
+        None => "the file header matches no known audio container".into(),
+    }
+}
+
+fn codec_failure_reason(codec: CodecType, err: &SymError) -> String {
+    if codec == codecs::CODEC_TYPE_OPUS && cfg!(not(feature = "opus")) {
+        "this build has no Opus decoder (built without the `opus` feature)".into()
+    } else {
+        err.to_string()
+    }
+}
+
+/// Describes the WAV `fmt ` chunk (format tag, bit depth) when it is in the header
+fn describe_wav_format(h: &[u8]) -> Option<String> {
+    if h.len() < 12 || &h[0..4] != b"RIFF" || &h[8..12] != b"WAVE" { return None; }
+    let mut i = 12;
+    while i + 8 <= h.len() {
+        let id = &h[i..i + 4];
+        let size = u32::from_le_bytes([h[i + 4], h[i + 5], h[i + 6], h[i + 7]]) as usize;
+        if id == b"fmt " && i + 8 + 16 <= h.len() {
+            let c = &h[i + 8..];
+            let mut tag = u16::from_le_bytes([c[0], c[1]]);
+            let bits = u16::from_le_bytes([c[14], c[15]]);
+            // WAVE_FORMAT_EXTENSIBLE: the real format is the first two bytes of the sub-format GUID
+            if tag == 0xFFFE && c.len() >= 26 {
+                tag = u16::from_le_bytes([c[24], c[25]]);
+            }
+            let name = match tag {
+                0x0001 => "PCM",
+                0x0003 => "IEEE float",
+                0x0002 => "MS ADPCM",
+                0x0011 => "IMA ADPCM",
+                0x0006 => "A-law",
+                0x0007 => "mu-law",
+                0x0055 => "MPEG Layer 3",
+                0x0161 => "WMA",
+                _ => "unknown",
+            };
+            return Some(format!("WAV format tag 0x{tag:04X} ({name}), {bits}-bit"));
+        }
+        i += 8 + size + (size & 1);
+    }
+    None
+}
+
+fn codec_name(codec: CodecType) -> String {
+    match CODECS.get_codec(codec) {
+        Some(d) => d.long_name.to_string(),
+        None if codec == codecs::CODEC_TYPE_OPUS => "Opus".into(),
+        None => format!("unknown ({codec})"),
+    }
+}
@@ -0,0 +1,206 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, FromSample, OutputCallbackInfo, SampleFormat, SizedSample, StreamConfig, SupportedBufferSize};
+use parking_lot::Mutex;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc,
+};
+
+type BoxedSource = Box<dyn Iterator<Item = f32> + Send>;
+
+// What the device callback plays: the interleaved source in the output format, or silence
+#[derive(Default)]
+struct Slot {
+    source: Option<BoxedSource>,
+    paused: bool,
+}
+
+/// Latency figures measured by the device callback, readable from any thread
+#[derive(Default)]
+pub struct OutputTiming {
+    latency_us: AtomicU64,     // callback -> playback delay reported by the host (0 = not reported)
+    callback_frames: AtomicU32,// frames requested by the last callback
+    failed: AtomicBool,        // the stream reported an error (device unplugged, reconfigured...)
+}
+
+impl OutputTiming {
+    /// Device latency in ms: the delay reported by the host, at least one callback buffer
+    pub fn latency_ms(&self, sample_rate: u32) -> Option<f64> {
+        let frames = self.callback_frames.load(Ordering::Relaxed);
+        if frames == 0 { return None; }
+        let buffer_ms = frames as f64 * 1000.0 / sample_rate.max(1) as f64;
+        let reported_ms = self.latency_us.load(Ordering::Relaxed) as f64 / 1000.0;
+        Some(reported_ms.max(buffer_ms))
+    }
+
+    pub fn callback_frames(&self) -> Option<u32> {
+        match self.callback_frames.load(Ordering::Relaxed) {
+            0 => None,
+            n => Some(n),
+        }
+    }
+
+    fn reset(&self) {
+        self.latency_us.store(0, Ordering::Relaxed);
+        self.callback_frames.store(0, Ordering::Relaxed);
+        self.failed.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Default output device opened with an optional fixed buffer size
+pub struct AudioOutput {
+    _stream: cpal::Stream,
+    slot: Arc<Mutex<Slot>>,
+    timing: Arc<OutputTiming>,
+    pub device_name: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Buffer size actually requested from the device (None = host default)
+    pub buffer_frames: Option<u32>,
+    /// Range accepted by the device, when the host reports it
+    pub buffer_range: Option<(u32, u32)>,
+}
+
+impl AudioOutput {
+    /// Opens the default device in its default format. `buffer_frames` is clamped to what the
+    /// device supports; if the device refuses a fixed size, the host default is used.
+    pub fn open(buffer_frames: Option<u32>, timing: Arc<OutputTiming>) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = host.default_output_device().ok_or("No default output device available")?;
+        let supported = device.default_output_config()
+            .map_err(|e| format!("Could not get output config: {e}"))?;
+
+        let buffer_range = match supported.buffer_size() {
+            SupportedBufferSize::Range { min, max } => Some((*min, *max)),
+            SupportedBufferSize::Unknown => None,
+        };
+        let fixed = buffer_frames.map(|n| match buffer_range {
+            Some((min, max)) => n.clamp(min, max),
+            None => n.max(16),
+        });
+
+        let device_name = device.name().ok();
+        let format = supported.sample_format();
+        let mut config: StreamConfig = supported.config();
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        timing.reset();
+
+        config.buffer_size = fixed.map(BufferSize::Fixed).unwrap_or(BufferSize::Default);
+        let (stream, fixed) = match build_stream(&device, &config, format, slot.clone(), timing.clone()) {
+            Ok(s) => (s, fixed),
+            Err(e) if fixed.is_some() => {
+                eprintln!("[audio] buffer of {} frames refused ({e}), using the device default", fixed.unwrap_or(0));
+                config.buffer_size = BufferSize::Default;
+                (build_stream(&device, &config, format, slot.clone(), timing.clone())?, None)
+            }
+            Err(e) => return Err(e),
+        };
+        stream.play().map_err(|e| format!("Could not start audio output: {e}"))?;
+
+        Ok(Self {
+            _stream: stream,
+            slot,
+            timing,
+            device_name,
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+            buffer_frames: fixed,
+            buffer_range,
+        })
+    }
+
+    /// Replaces what is playing (the previous source is dropped outside the device callback)
+    pub fn play_source<S: Iterator<Item = f32> + Send + 'static>(&self, src: S) {
+        let old = {
+            let mut slot = self.slot.lock();
+            slot.paused = false;
+            slot.source.replace(Box::new(src))
+        };
+        drop(old);
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.slot.lock().paused = paused;
+    }
+
+    pub fn clear(&self) {
+        let old = self.slot.lock().source.take();
+        drop(old);
+    }
+
+    /// True when the stream failed, or the default device or its format is no longer the one
+    /// this output was opened with
+    pub fn device_changed(&self) -> bool {
+        if self.timing.failed.load(Ordering::Relaxed) { return true; }
+        let Some(device) = cpal::default_host().default_output_device() else { return true };
+        if device.name().ok() != self.device_name { return true; }
+        match device.default_output_config() {
+            Ok(cfg) => cfg.sample_rate().0 != self.sample_rate || cfg.channels() != self.channels,
+            Err(_) => false, // busy devices may refuse the query; the error callback covers real failures
+        }
+    }
+}
+
+fn build_stream(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+    slot: Arc<Mutex<Slot>>,
+    timing: Arc<OutputTiming>,
+) -> Result<cpal::Stream, String> {
+    match format {
+        SampleFormat::F32 => build_typed::<f32>(device, config, slot, timing),
+        SampleFormat::F64 => build_typed::<f64>(device, config, slot, timing),
+        SampleFormat::I16 => build_typed::<i16>(device, config, slot, timing),
+        SampleFormat::U16 => build_typed::<u16>(device, config, slot, timing),
+        SampleFormat::I32 => build_typed::<i32>(device, config, slot, timing),
+        SampleFormat::U32 => build_typed::<u32>(device, config, slot, timing),
+        SampleFormat::I8 => build_typed::<i8>(device, config, slot, timing),
+        SampleFormat::U8 => build_typed::<u8>(device, config, slot, timing),
+        other => Err(format!("Unsupported output sample format {other}")),
+    }
+}
+
+fn build_typed<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    slot: Arc<Mutex<Slot>>,
+    timing: Arc<OutputTiming>,
+) -> Result<cpal::Stream, String>
+where
+    T: SizedSample + FromSample<f32> + Send + 'static,
+{
+    let channels = config.channels.max(1) as usize;
+    let silence = T::from_sample(0.0f32);
+    let on_error = timing.clone();
+    device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], info: &OutputCallbackInfo| {
+                let ts = info.timestamp();
+                if let Some(d) = ts.playback.duration_since(&ts.callback) {
+                    timing.latency_us.store(d.as_micros() as u64, Ordering::Relaxed);
+                }
+                timing.callback_frames.store((data.len() / channels) as u32, Ordering::Relaxed);
+
+                let mut slot = slot.lock();
+                let Slot { source, paused } = &mut *slot;
+                let mut written = 0;
+                if let (Some(src), false) = (source.as_mut(), *paused) {
+                    for s in data.iter_mut() {
+                        let Some(v) = src.next() else { break };
+                        *s = T::from_sample(v);
+                        written += 1;
+                    }
+                    if written < data.len() { *source = None; } // finished
+                }
+                data[written..].fill(silence);
+            },
+            move |e| {
+                eprintln!("[audio] output stream error: {e}");
+                on_error.failed.store(true, Ordering::Relaxed);
+            },
+            None,
+        )
+        .map_err(|e| format!("Could not open audio output: {e}"))
+}
@@ -1,41 +1,224 @@
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use parking_lot::Mutex;
-use crossbeam_channel::{unbounded, bounded, Sender, Receiver};
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
-use std::{fs::File, io::BufReader, path::Path, time::Instant};
-use serde::Serialize;
+use crossbeam_channel::{unbounded, bounded, Sender, Receiver, RecvTimeoutError};
+use std::{collections::VecDeque, path::Path, time::{Duration, Instant}};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+use crate::audio_decode::AudioDecoder;
+use crate::audio_output::{AudioOutput, OutputTiming};
+use crate::song_manifest;
 
 // ---------- Public API (what Tauri commands will use) ----------
 
-pub static AUDIO: Lazy<AudioService> = Lazy::new(|| AudioService::new());
+pub static AUDIO: Lazy<AudioService> = Lazy::new(AudioService::new);
+
+// Used by the audio thread to emit events; set once the Tauri app is running
+static APP_HANDLE: OnceCell<AppHandle> = OnceCell::new();
+
+/// Emitted when playback moves on to the next queued song
+pub const SONG_CHANGED_EVENT: &str = "audio-song-changed";
+
+/// Emitted when the output device or its format changed and the output was rebuilt
+pub const OUTPUT_CHANGED_EVENT: &str = "audio-output-changed";
+
+/// How often the audio thread checks the default output device
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Clone)]
+pub struct SongChangedEvent {
+    pub paths: Vec<String>,
+    pub song_dir: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OutputChangedEvent {
+    pub device: Option<String>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub error: Option<String>, // set if the new output could not be opened; playback is paused
+}
 
 #[derive(Serialize, Clone, Copy)]
 pub struct AudioStatus {
     pub position_secs: f64,
     pub is_playing: bool,
+    pub output_latency_ms: Option<f64>, // device latency, None until the output has run
+    pub buffer_frames: Option<u32>,     // frames per device callback
+    pub block_frames: usize,
+}
+
+/// Output buffering. Smaller buffers lower the latency but may crackle on slow devices.
+#[derive(Serialize, Clone, Copy)]
+pub struct OutputSettings {
+    pub buffer_frames: Option<u32>, // None = device default
+    pub block_frames: usize,        // mixing block size
+    pub min_buffer_frames: Option<u32>,
+    pub max_buffer_frames: Option<u32>,
+}
+
+/// Peak and RMS level (linear, 0.0..=1.0 nominal) of one track or of the master bus
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct LevelMeter {
+    pub peak: f32, // highest absolute sample since the last meters read
+    pub rms: f32,  // RMS of the last rendered block
+}
+
+#[derive(Serialize, Clone)]
+pub struct TrackMeter {
+    pub path: String,
+    pub peak: f32,
+    pub rms: f32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AudioMeters {
+    pub master: LevelMeter,
+    pub tracks: Vec<TrackMeter>, // same order as the loaded paths
+}
+
+/// Mixer state of one track. In saved presets `path` is relative to the song folder.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrackMix {
+    pub path: String,
+    pub gain: f32,
+    pub muted: bool,
+    pub pan: f32, // -1.0 = left, 0.0 = center, 1.0 = right
+    #[serde(default)]
+    pub offset_ms: f32, // > 0 delays the track, < 0 makes it start earlier
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub automation: Vec<AutomationPoint>,
+}
+
+/// Gain multiplier at a song position. Between points the gain is interpolated linearly;
+/// before the first and after the last point their gain is held.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct AutomationPoint {
+    pub time_secs: f64,
+    pub gain: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LoopRegion {
+    pub start_secs: f64,
+    pub end_secs: f64,
+}
+
+/// Practice setup of a song: per-track mix, playback rate and loop region
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MixSettings {
+    pub tracks: Vec<TrackMix>,
+    pub playback_rate: f32,
+    pub loop_region: Option<LoopRegion>,
+}
+
+impl Default for MixSettings {
+    fn default() -> Self {
+        Self { tracks: Vec::new(), playback_rate: 1.0, loop_region: None }
+    }
 }
 
+/// A song waiting in the setlist queue
+#[derive(Serialize, Clone)]
+pub struct QueuedSong {
+    pub paths: Vec<String>,
+    pub song_dir: Option<String>,
+    pub duration_secs: Option<f64>,
+}
+
+/// Key under which the mix preset is stored in the song manifest
+const MANIFEST_MIX_KEY: &str = "mix";
+
+/// Largest per-track offset accepted, either way
+const MAX_TRACK_OFFSET_MS: f32 = 10_000.0;
+
+/// Accepted mixing block sizes, in frames
+const MIN_BLOCK_FRAMES: usize = 64;
+const MAX_BLOCK_FRAMES: usize = 8192;
+
 pub struct AudioService {
     tx: Sender<AudioCmd>,
     shared: ArcShared,
+    meters: ArcMeters,
+    timing: std::sync::Arc<OutputTiming>,
 }
 
 type ArcShared = std::sync::Arc<Mutex<Shared>>;
+type ArcMeters = std::sync::Arc<Mutex<Meters>>;
+type ArcAutomation = std::sync::Arc<Mutex<Automation>>;
 
 impl AudioService {
     fn new() -> Self {
         let (tx, rx) = unbounded::<AudioCmd>();
         let shared = std::sync::Arc::new(Mutex::new(Shared::default()));
+        let meters = std::sync::Arc::new(Mutex::new(Meters::default()));
+        let timing = std::sync::Arc::new(OutputTiming::default());
         std::thread::spawn({
             let shared = shared.clone();
-            move || audio_thread(rx, shared)
+            let meters = meters.clone();
+            let timing = timing.clone();
+            let tx = tx.clone(); // the mix source reports song changes through the command queue
+            move || audio_thread(rx, tx, shared, meters, timing)
         });
-        Self { tx, shared }
+        Self { tx, shared, meters, timing }
+    }
+
+    pub fn set_app_handle(&self, app: AppHandle) {
+        let _ = APP_HANDLE.set(app);
+    }
+
+    // Load doesn't block: enqueues and returns immediately.
+    // With `song_dir`, the mix preset saved in that song folder is recalled after loading.
+    // `duration_secs` is only needed to crossfade into a queued song.
+    pub fn load(&self, paths: Vec<String>, song_dir: Option<String>, duration_secs: Option<f64>) -> Result<(), String> {
+        self.tx.send(AudioCmd::Load { paths, song_dir, duration_secs }).map_err(|e| e.to_string())
+    }
+
+    /// Appends a song to the setlist; it starts when the current one ends (or crossfades into it)
+    pub fn queue_song(&self, song: QueuedSong) -> Result<(), String> {
+        if song.paths.is_empty() { return Err("At least one audio path is required".into()); }
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::QueueSong { song, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn clear_song_queue(&self) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::ClearQueue { resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn song_queue(&self) -> Result<Vec<QueuedSong>, String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::GetQueue { resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
     }
 
-    // Load doesn't block: enqueues and returns immediately
-    pub fn load(&self, paths: Vec<String>) -> Result<(), String> {
-        self.tx.send(AudioCmd::Load { paths }).map_err(|e| e.to_string())
+    /// 0 = gapless. Crossfades need the duration of the outgoing song.
+    pub fn set_crossfade(&self, ms: u32) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetCrossfade { ms, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    /// Device buffer size in frames (None = device default). Reopens the output at the same position.
+    pub fn set_output_buffer_size(&self, frames: Option<u32>) -> Result<OutputSettings, String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetBufferSize { frames, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn set_mix_block_size(&self, frames: usize) -> Result<OutputSettings, String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetBlockSize { frames, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn output_settings(&self) -> Result<OutputSettings, String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::GetOutputSettings { resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
     }
 
     pub fn play(&self) -> Result<(), String> {
@@ -81,27 +264,116 @@ impl AudioService {
         rrx.recv().map_err(|e| e.to_string())?
     }
 
+    pub fn set_tracks_pan_by_path(&self, paths: Vec<String>, pan: f32) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetTracksPanByPath { paths, pan, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn set_tracks_offset_by_path(&self, paths: Vec<String>, offset_ms: f32) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetTracksOffsetByPath { paths, offset_ms, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    /// Replaces the automation envelope of the given tracks (an empty list clears it)
+    pub fn set_tracks_automation_by_path(&self, paths: Vec<String>, points: Vec<AutomationPoint>) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetTracksAutomationByPath { paths, points, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn set_playback_rate(&self, rate: f32) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetPlaybackRate { rate, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn set_loop_region(&self, region: Option<LoopRegion>) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::SetLoopRegion { region, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    pub fn mix_settings(&self) -> Result<MixSettings, String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::GetMix { resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    // Tracks are matched by full path, as in the other *_by_path calls
+    pub fn apply_mix_settings(&self, settings: MixSettings) -> Result<(), String> {
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::ApplyMix { settings, song_dir: None, resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())?
+    }
+
+    /// Stores the current mix in `<song_dir>/parasync.json`, with track paths relative to `song_dir`
+    pub fn save_song_mix(&self, song_dir: String) -> Result<(), String> {
+        let dir = Path::new(&song_dir);
+        let mut settings = self.mix_settings()?;
+        for t in &mut settings.tracks {
+            t.path = track_key(&t.path, dir);
+        }
+        let value = serde_json::to_value(&settings).map_err(|e| format!("Failed to serialize mix: {e}"))?;
+        song_manifest::update_manifest(dir, |m| {
+            m.insert(MANIFEST_MIX_KEY.into(), value);
+        })
+    }
+
+    /// Applies the mix saved in `<song_dir>/parasync.json`. Returns false if the song has none.
+    pub fn load_song_mix(&self, song_dir: String) -> Result<bool, String> {
+        let Some(settings) = read_song_mix(Path::new(&song_dir))? else { return Ok(false) };
+        let (rtx, rrx) = bounded(1);
+        self.tx.send(AudioCmd::ApplyMix { settings, song_dir: Some(song_dir), resp: rtx }).map_err(|e| e.to_string())?;
+        rrx.recv().map_err(|e| e.to_string())??;
+        Ok(true)
+    }
+
     pub fn status(&self) -> AudioStatus {
         let sh = self.shared.lock();
         let sr = sh.out_sample_rate.max(1) as f64;
         let mut pos_frames = sh.pos_frames_base as f64;
         if sh.is_playing {
             if let Some(t0) = sh.play_started_at {
-                pos_frames += (Instant::now() - t0).as_secs_f64() * sh.out_sample_rate as f64;
+                pos_frames = advance_position(
+                    sh.pos_frames_base,
+                    (Instant::now() - t0).as_secs_f64(),
+                    sh.out_sample_rate,
+                    sh.rate,
+                    sh.loop_frames,
+                );
             }
         }
         let pos = pos_frames / sr;
         AudioStatus {
             position_secs: pos,
             is_playing: sh.is_playing,
+            output_latency_ms: self.timing.latency_ms(sh.out_sample_rate),
+            buffer_frames: self.timing.callback_frames(),
+            block_frames: sh.block_frames,
         }
     }
+
+    // Reading the meters resets the held peaks, so each poll sees the peaks since the previous one
+    pub fn meters(&self) -> AudioMeters {
+        let mut guard = self.meters.lock();
+        let m = &mut *guard;
+        let master = m.master;
+        m.master.peak = 0.0;
+        let mut tracks = Vec::with_capacity(m.tracks.len());
+        for (path, lv) in m.paths.iter().zip(m.tracks.iter_mut()) {
+            tracks.push(TrackMeter { path: path.clone(), peak: lv.peak, rms: lv.rms });
+            lv.peak = 0.0;
+        }
+        AudioMeters { master, tracks }
+    }
 }
 
 // ---------- Messages and shared state ----------
 
 enum AudioCmd {
-    Load { paths: Vec<String> }, // no response: fire-and-forget
+    Load { paths: Vec<String>, song_dir: Option<String>, duration_secs: Option<f64> }, // no response: fire-and-forget
     Play { resp: Sender<Result<(), String>> },
     Pause { resp: Sender<Result<(), String>> },
     Stop  { resp: Sender<Result<(), String>> },
@@ -111,6 +383,28 @@ enum AudioCmd {
     // new commands for gain/mute by path
     SetTracksGainByPath { paths: Vec<String>, gain: f32, ramp_ms: Option<u32>, resp: Sender<Result<(), String>> },
     MuteTracksByPath    { paths: Vec<String>, muted: bool, ramp_ms: Option<u32>, resp: Sender<Result<(), String>> },
+    SetTracksPanByPath  { paths: Vec<String>, pan: f32, resp: Sender<Result<(), String>> },
+    SetTracksOffsetByPath { paths: Vec<String>, offset_ms: f32, resp: Sender<Result<(), String>> },
+    SetTracksAutomationByPath { paths: Vec<String>, points: Vec<AutomationPoint>, resp: Sender<Result<(), String>> },
+
+    // practice settings
+    SetPlaybackRate { rate: f32, resp: Sender<Result<(), String>> },
+    SetLoopRegion   { region: Option<LoopRegion>, resp: Sender<Result<(), String>> },
+    LoopWrapped     { spent: Vec<TrackStream>, start: std::sync::Arc<LoopStart> }, // sent by a looping source after jumping back
+    GetMix   { resp: Sender<Result<MixSettings, String>> },
+    ApplyMix { settings: MixSettings, song_dir: Option<String>, resp: Sender<Result<(), String>> },
+
+    // setlist
+    QueueSong  { song: QueuedSong, resp: Sender<Result<(), String>> },
+    ClearQueue { resp: Sender<Result<(), String>> },
+    GetQueue   { resp: Sender<Result<Vec<QueuedSong>, String>> },
+    SetCrossfade { ms: u32, resp: Sender<Result<(), String>> },
+    SongChanged, // sent by the playing source when it switches to the queued song
+
+    // output
+    SetBufferSize { frames: Option<u32>, resp: Sender<Result<OutputSettings, String>> },
+    SetBlockSize  { frames: usize, resp: Sender<Result<OutputSettings, String>> },
+    GetOutputSettings { resp: Sender<Result<OutputSettings, String>> },
 }
 
 #[derive(Default)]
@@ -120,6 +414,30 @@ struct Shared {
     pos_frames_base: usize,  // accumulated frames (doesn't include dt of current playback)
     play_started_at: Option<Instant>,
     is_playing: bool,
+    rate: f64,                            // song frames advanced per output frame
+    loop_frames: Option<(usize, usize)>,  // active loop region in frames
+    block_frames: usize,
+}
+
+// Levels written by the mix source after every block (same order as `paths`)
+#[derive(Default)]
+struct Meters {
+    paths: Vec<String>,
+    tracks: Vec<LevelMeter>,
+    master: LevelMeter,
+}
+
+impl Meters {
+    fn reset(&mut self, paths: &[String]) {
+        self.paths = paths.to_vec();
+        self.tracks = vec![LevelMeter::default(); paths.len()];
+        self.master = LevelMeter::default();
+    }
+
+    fn silence(&mut self) {
+        for lv in &mut self.tracks { *lv = LevelMeter::default(); }
+        self.master = LevelMeter::default();
+    }
 }
 
 // Gain control per track (shared between Engine and mix source)
@@ -128,6 +446,67 @@ struct GainCmd {
     target: f32,        // target (0.0 = mute, 1.0 = unity, >1.0 allowed if desired)
     ramp_frames: usize, // how many frames to take to reach target
     gen: u64,           // version number to detect changes
+    pan: f32,           // -1.0..=1.0, applied immediately (no ramp)
+}
+
+// Automation envelopes per track (same order as `paths`), versioned like `GainCmd`
+#[derive(Default)]
+struct Automation {
+    gen: u64,
+    tracks: Vec<Vec<AutomationPoint>>,
+}
+
+// Queued song with everything needed to start it: its own mix state, recalled from its preset
+struct SongEntry {
+    paths: Vec<String>,
+    song_dir: Option<String>,
+    total_frames: usize,
+    mix: Vec<TrackMix>,
+    rate: f32,
+    loop_region: Option<LoopRegion>,
+    gains: std::sync::Arc<Mutex<Vec<GainCmd>>>,
+    automation: ArcAutomation,
+}
+
+// Next song handed to the playing source, plus the crossfade length
+#[derive(Default)]
+struct Handoff {
+    next: Option<MixedSource>,
+    crossfade_frames: usize,
+}
+
+type ArcHandoff = std::sync::Arc<Mutex<Handoff>>;
+
+// Tracks of a looping source reopened at the loop start. They are opened on the engine thread,
+// ahead of time, so the render path only swaps them in.
+struct LoopStart {
+    paths: Vec<String>,
+    offsets: Vec<f64>,
+    out_sr: u32,
+    out_ch: u16,
+    rate: f32,
+    start_sec: f64,
+    ready: Mutex<Option<Vec<TrackStream>>>,
+}
+
+impl LoopStart {
+    fn prepare(&self) {
+        match open_tracks(&self.paths, &self.offsets, self.out_sr, self.out_ch, self.rate, self.start_sec) {
+            Ok(tracks) => *self.ready.lock() = Some(tracks),
+            Err(e) => eprintln!("[audio] could not reopen the loop start: {e}"),
+        }
+    }
+}
+
+// Render parameters fixed for the lifetime of one mix source
+#[derive(Clone, Copy)]
+struct MixParams {
+    out_sr: u32,
+    out_ch: u16,
+    block_frames: usize,
+    start_sec: f64,
+    rate: f32,
+    loop_region: Option<LoopRegion>,
 }
 
 // ---------- Audio thread (owns everything that is NOT Send) ----------
@@ -136,52 +515,103 @@ struct GainCmd {
 enum PlayState { Stopped, Playing, Paused }
 
 struct Engine {
-    stream: Option<OutputStream>,
-    handle: Option<OutputStreamHandle>,
+    output: Option<AudioOutput>,
+    timing: std::sync::Arc<OutputTiming>,
+    buffer_frames: Option<u32>, // requested device buffer (None = device default)
     out_sr: u32,
     out_ch: u16,
 
     // streaming: we save paths
     paths: Vec<String>,
+    song_dir: Option<String>,
     total_frames: usize,   // 0 = unknown (we don't return it)
 
     // shared gains per track (same order as `paths`)
     gains: std::sync::Arc<Mutex<Vec<GainCmd>>>,
+    // levels per track and master, filled by the mix source
+    meters: ArcMeters,
+    // gain envelopes per track, read by the mix source
+    automation: ArcAutomation,
 
-    sink: Option<Sink>,
+    // user mix per track (same order as `paths`); `gains` is derived from it
+    mix: Vec<TrackMix>,
+    rate: f32,
+    loop_region: Option<LoopRegion>,
+
+    has_source: bool,      // a mix source is in the output (possibly paused)
     state: PlayState,
     pos_frames: usize,     // target position (frames out_sr)
     play_started_at: Option<Instant>,
 
+    // setlist
+    queue: VecDeque<SongEntry>,
+    crossfade_ms: u32,
+    handoff: ArcHandoff,   // shared with the playing source
+    tx: Sender<AudioCmd>,
+
     // mixing
     block_frames: usize,   // block size for render (e.g. 1024)
 
     shared: ArcShared,
 }
 
-fn audio_thread(rx: Receiver<AudioCmd>, shared: ArcShared) {
+fn audio_thread(rx: Receiver<AudioCmd>, tx: Sender<AudioCmd>, shared: ArcShared, meters: ArcMeters, timing: std::sync::Arc<OutputTiming>) {
     let (sr, ch) = default_output_format().unwrap_or((48000, 2));
     let mut eng = Engine {
-        stream: None,
-        handle: None,
+        output: None,
+        timing,
+        buffer_frames: None,
         out_sr: sr,
         out_ch: ch,
         paths: Vec::new(),
+        song_dir: None,
         total_frames: 0,
         gains: std::sync::Arc::new(Mutex::new(Vec::new())),
-        sink: None,
+        meters,
+        automation: std::sync::Arc::new(Mutex::new(Automation::default())),
+        mix: Vec::new(),
+        rate: 1.0,
+        loop_region: None,
+        has_source: false,
         state: PlayState::Stopped,
         pos_frames: 0,
         play_started_at: None,
+        queue: VecDeque::new(),
+        crossfade_ms: 0,
+        handoff: std::sync::Arc::new(Mutex::new(Handoff::default())),
+        tx,
         block_frames: 1024, // ~21ms @48k
         shared,
     };
     eng.push_shared();
 
-    while let Ok(cmd) = rx.recv() {
+    let mut last_device_check = Instant::now();
+    loop {
+        // wake up regularly even without commands, to notice device changes
+        let cmd = match rx.recv_timeout(DEVICE_POLL_INTERVAL) {
+            Ok(cmd) => Some(cmd),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if last_device_check.elapsed() >= DEVICE_POLL_INTERVAL {
+            last_device_check = Instant::now();
+            if eng.output.as_ref().is_some_and(|o| o.device_changed()) {
+                eng.rebuild_output();
+                eng.push_shared();
+            }
+        }
+        let Some(cmd) = cmd else { continue };
+
         match cmd {
-            AudioCmd::Load { paths } => {
-                let _ = eng.prepare_streaming(paths); // fast, without decoding everything
+            AudioCmd::Load { paths, song_dir, duration_secs } => {
+                // fast, without decoding everything
+                if eng.prepare_streaming(paths, song_dir.clone(), duration_secs).is_ok() {
+                    if let Some(dir) = song_dir {
+                        if let Ok(Some(settings)) = read_song_mix(Path::new(&dir)) {
+                            let _ = eng.apply_mix(settings, Some(Path::new(&dir)));
+                        }
+                    }
+                }
                 eng.push_shared();
             }
             AudioCmd::Play { resp } => {
@@ -205,9 +635,75 @@ fn audio_thread(rx: Receiver<AudioCmd>, shared: ArcShared) {
                 eng.push_shared(); let _ = resp.send(r);
             }
             AudioCmd::MuteTracksByPath { paths, muted, ramp_ms, resp } => {
-                let r = eng.set_tracks_gain_by_path(paths, if muted { 0.0 } else { 1.0 }, ramp_ms);
+                let r = eng.mute_tracks_by_path(paths, muted, ramp_ms);
                 eng.push_shared(); let _ = resp.send(r);
             }
+            AudioCmd::SetTracksPanByPath { paths, pan, resp } => {
+                let r = eng.set_tracks_pan_by_path(paths, pan);
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::SetTracksOffsetByPath { paths, offset_ms, resp } => {
+                let r = eng.set_tracks_offset_by_path(paths, offset_ms);
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::SetTracksAutomationByPath { paths, points, resp } => {
+                let r = eng.set_tracks_automation_by_path(paths, points);
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::SetPlaybackRate { rate, resp } => {
+                let r = eng.set_playback_rate(rate);
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::SetLoopRegion { region, resp } => {
+                let r = eng.set_loop_region(region);
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::LoopWrapped { spent, start } => {
+                // close the played tracks here rather than on the render thread
+                drop(spent);
+                // reopen the loop start for the next pass, unless the source is gone
+                if std::sync::Arc::strong_count(&start) > 1 { start.prepare(); }
+            }
+            AudioCmd::GetMix { resp } => {
+                let _ = resp.send(Ok(eng.mix_settings()));
+            }
+            AudioCmd::ApplyMix { settings, song_dir, resp } => {
+                let r = eng.apply_mix(settings, song_dir.as_deref().map(Path::new));
+                eng.push_shared(); let _ = resp.send(r);
+            }
+
+            AudioCmd::QueueSong { song, resp } => {
+                let _ = resp.send(eng.queue_song(song));
+            }
+            AudioCmd::ClearQueue { resp } => {
+                eng.queue.clear();
+                eng.arm_next();
+                let _ = resp.send(Ok(()));
+            }
+            AudioCmd::GetQueue { resp } => {
+                let _ = resp.send(Ok(eng.queue.iter().map(|e| e.describe(eng.out_sr)).collect()));
+            }
+            AudioCmd::SetCrossfade { ms, resp } => {
+                eng.crossfade_ms = ms;
+                eng.handoff.lock().crossfade_frames = eng.crossfade_frames();
+                let _ = resp.send(Ok(()));
+            }
+            AudioCmd::SongChanged => {
+                eng.advance_queue();
+                eng.push_shared();
+            }
+
+            AudioCmd::SetBufferSize { frames, resp } => {
+                let r = eng.set_buffer_size(frames).map(|_| eng.output_settings());
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::SetBlockSize { frames, resp } => {
+                let r = eng.set_block_size(frames).map(|_| eng.output_settings());
+                eng.push_shared(); let _ = resp.send(r);
+            }
+            AudioCmd::GetOutputSettings { resp } => {
+                let _ = resp.send(Ok(eng.output_settings()));
+            }
         };
     }
 }
@@ -220,33 +716,113 @@ impl Engine {
         sh.pos_frames_base = self.pos_frames;
         sh.play_started_at = self.play_started_at;
         sh.is_playing = self.state == PlayState::Playing;
+        sh.rate = self.rate as f64;
+        sh.loop_frames = self.loop_frames();
+        sh.block_frames = self.block_frames;
+    }
+
+    fn loop_frames(&self) -> Option<(usize, usize)> {
+        self.loop_region.map(|r| {
+            let sr = self.out_sr as f64;
+            ((r.start_secs * sr).round() as usize, (r.end_secs * sr).round() as usize)
+        })
     }
 
     fn ensure_output(&mut self) -> Result<(), String> {
-        if self.stream.is_none() || self.handle.is_none() {
-            let (stream, handle) = OutputStream::try_default()
-                .map_err(|e| format!("Could not open audio output: {e}"))?;
-            self.stream = Some(stream);
-            self.handle = Some(handle);
+        if self.output.is_none() {
+            let output = AudioOutput::open(self.buffer_frames, self.timing.clone())?;
+            // positions are kept in output frames: rescale them if the device rate differs
+            if output.sample_rate != self.out_sr {
+                let scale = output.sample_rate as f64 / self.out_sr.max(1) as f64;
+                self.pos_frames = (self.pos_frames as f64 * scale).round() as usize;
+                self.total_frames = (self.total_frames as f64 * scale).round() as usize;
+                for e in &mut self.queue {
+                    e.total_frames = (e.total_frames as f64 * scale).round() as usize;
+                }
+            }
+            self.out_sr = output.sample_rate;
+            self.out_ch = output.channels;
+            self.output = Some(output);
+        }
+        Ok(())
+    }
+
+    fn output_settings(&self) -> OutputSettings {
+        let range = self.output.as_ref().and_then(|o| o.buffer_range);
+        OutputSettings {
+            buffer_frames: self.output.as_ref().map_or(self.buffer_frames, |o| o.buffer_frames),
+            block_frames: self.block_frames,
+            min_buffer_frames: range.map(|r| r.0),
+            max_buffer_frames: range.map(|r| r.1),
+        }
+    }
+
+    // The device or its format changed under us: reopen the default device and rebuild the mix
+    // sources for its rate/channels at the same song position
+    fn rebuild_output(&mut self) {
+        self.sync_position();
+        self.kill_sink();
+        self.output = None;
+        let result = self.ensure_output().and_then(|_| self.restart_stream());
+        if let Err(e) = &result {
+            eprintln!("[audio] could not reopen the output: {e}");
+            let _ = self.pause();
+        }
+
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit(OUTPUT_CHANGED_EVENT, OutputChangedEvent {
+                device: self.output.as_ref().and_then(|o| o.device_name.clone()),
+                sample_rate: self.out_sr,
+                channels: self.out_ch,
+                error: result.err(),
+            });
+        }
+    }
+
+    // The buffer size is fixed when the device stream is built: reopen it at the current position
+    fn set_buffer_size(&mut self, frames: Option<u32>) -> Result<(), String> {
+        if frames == Some(0) { return Err("Buffer size must be at least 1 frame".into()); }
+        self.buffer_frames = frames;
+        if self.output.is_none() { return Ok(()); }
+        self.sync_position();
+        self.kill_sink();
+        self.output = None;
+        self.ensure_output()?;
+        self.restart_stream()
+    }
+
+    fn set_block_size(&mut self, frames: usize) -> Result<(), String> {
+        let frames = frames.clamp(MIN_BLOCK_FRAMES, MAX_BLOCK_FRAMES);
+        if frames != self.block_frames {
+            self.sync_position();
+            self.block_frames = frames;
+            self.restart_stream()?;
         }
         Ok(())
     }
 
     // "Light" load: only saves paths; does NOT calculate duration
-    fn prepare_streaming(&mut self, paths: Vec<String>) -> Result<(), String> {
+    fn prepare_streaming(&mut self, paths: Vec<String>, song_dir: Option<String>, duration_secs: Option<f64>) -> Result<(), String> {
         self.ensure_output()?;
         if paths.is_empty() { return Err("At least one audio path is required".into()); }
-        self.total_frames = 0; // unknown (you have it in the frontend)
+        self.total_frames = duration_to_frames(duration_secs, self.out_sr); // 0 = unknown
         self.pos_frames = 0;
         self.state = PlayState::Stopped;
         self.kill_sink();
+        self.queue.clear();
 
-        // replace paths and reinitialize gains vector
+        // replace paths and reinitialize mix and gains vectors
         self.paths = paths;
+        self.song_dir = song_dir;
+        self.mix = default_mix(&self.paths);
+        self.push_automation();
+        self.rate = 1.0;
+        self.loop_region = None;
         let mut g = self.gains.lock();
         let len = self.paths.len();
-        let init = GainCmd { target: 1.0, ramp_frames: 0, gen: 0 };
-        *g = std::iter::repeat(init).take(len).collect::<Vec<_>>();
+        let init = GainCmd { target: 1.0, ramp_frames: 0, gen: 0, pan: 0.0 };
+        *g = vec![init; len];
+        self.meters.lock().reset(&self.paths);
 
         Ok(())
     }
@@ -258,8 +834,9 @@ impl Engine {
         match self.state {
             PlayState::Playing => {}
             PlayState::Paused => {
-                if let Some(sink) = &self.sink { sink.play(); }
-                else {
+                if self.has_source {
+                    if let Some(out) = &self.output { out.set_paused(false); }
+                } else {
                     let start_sec = self.pos_frames as f64 / self.out_sr as f64;
                     self.spawn_stream_from_time(start_sec)?;
                 }
@@ -279,14 +856,21 @@ impl Engine {
         if self.state == PlayState::Playing {
             if let Some(t0) = self.play_started_at.take() {
                 let dt = t0.elapsed();
-                let adv = (dt.as_secs_f64() * self.out_sr as f64).round() as usize;
+                let pos = advance_position(
+                    self.pos_frames,
+                    dt.as_secs_f64(),
+                    self.out_sr,
+                    self.rate as f64,
+                    self.loop_frames(),
+                ).round() as usize;
                 self.pos_frames = if self.total_frames > 0 {
-                    (self.pos_frames + adv).min(self.total_frames)
+                    pos.min(self.total_frames)
                 } else {
-                    self.pos_frames + adv
+                    pos
                 };
             }
-            if let Some(sink) = &self.sink { sink.pause(); }
+            if let Some(out) = &self.output { out.set_paused(true); }
+            self.meters.lock().silence();
             self.state = PlayState::Paused;
         }
         Ok(())
@@ -318,8 +902,13 @@ impl Engine {
 
     fn dispose(&mut self) -> Result<(), String> {
         self.kill_sink();
+        self.queue.clear();
         self.paths.clear();
+        self.song_dir = None;
         self.gains.lock().clear();
+        self.mix.clear();
+        self.push_automation();
+        self.meters.lock().reset(&[]);
         self.total_frames = 0;
         self.pos_frames = 0;
         self.state = PlayState::Stopped;
@@ -327,55 +916,420 @@ impl Engine {
     }
 
     fn spawn_stream_from_time(&mut self, start_sec: f64) -> Result<(), String> {
-        let handle = self.handle.as_ref().ok_or("OutputStreamHandle not initialized")?;
-        let src = MixedSource::new(
+        self.ensure_output()?;
+        let params = MixParams {
+            out_sr: self.out_sr,
+            out_ch: self.out_ch,
+            block_frames: self.block_frames,
+            start_sec,
+            rate: self.rate,
+            loop_region: self.loop_region,
+        };
+        let offsets = track_offsets(&self.mix);
+        let mut src = MixedSource::new(
             self.paths.clone(),
+            offsets,
             self.gains.clone(),
-            self.out_sr,
-            self.out_ch,
-            self.block_frames,
-            start_sec,
+            self.automation.clone(),
+            self.meters.clone(),
+            self.tx.clone(),
+            params,
         )?;
-        let sink = Sink::try_new(handle).map_err(|e| format!("Could not create Sink: {e}"))?;
-        sink.append(src);
-        sink.play();
-        self.sink = Some(sink);
+        src.end_frames = self.total_frames;
+
+        // a fresh handoff, so a source being replaced can't take the queued song
+        self.handoff = std::sync::Arc::new(Mutex::new(Handoff {
+            next: None,
+            crossfade_frames: self.crossfade_frames(),
+        }));
+        let src = SetlistSource::new(src, self.handoff.clone(), self.tx.clone());
+
+        let output = self.output.as_ref().ok_or("Audio output not initialized")?;
+        output.play_source(src);
+        self.has_source = true;
+        self.arm_next();
         Ok(())
     }
 
+    fn crossfade_frames(&self) -> usize {
+        (self.crossfade_ms as u64 * self.out_sr as u64 / 1000) as usize
+    }
+
+    fn queue_song(&mut self, song: QueuedSong) -> Result<(), String> {
+        let mut mix = default_mix(&song.paths);
+        let mut rate = 1.0;
+        let mut loop_region = None;
+        if let Some(dir) = &song.song_dir {
+            if let Some(settings) = read_song_mix(Path::new(dir))? {
+                merge_mix(&mut mix, &settings, Some(Path::new(dir)))?;
+                rate = settings.playback_rate.clamp(0.25, 2.0);
+                loop_region = settings.loop_region.filter(|r| r.end_secs > r.start_secs && r.start_secs >= 0.0);
+            }
+        }
+        let gains = mix.iter()
+            .map(|m| GainCmd { target: if m.muted { 0.0 } else { m.gain }, ramp_frames: 0, gen: 1, pan: m.pan })
+            .collect();
+        let automation = Automation { gen: 1, tracks: mix.iter().map(|m| m.automation.clone()).collect() };
+        self.queue.push_back(SongEntry {
+            total_frames: duration_to_frames(song.duration_secs, self.out_sr),
+            paths: song.paths,
+            song_dir: song.song_dir,
+            mix,
+            rate,
+            loop_region,
+            gains: std::sync::Arc::new(Mutex::new(gains)),
+            automation: std::sync::Arc::new(Mutex::new(automation)),
+        });
+        if self.queue.len() == 1 { self.arm_next(); }
+        Ok(())
+    }
+
+    // Opens the first queued song and hands it to the playing source
+    fn arm_next(&mut self) {
+        let next = match self.queue.front() {
+            Some(entry) if self.has_source => {
+                let params = MixParams {
+                    out_sr: self.out_sr,
+                    out_ch: self.out_ch,
+                    block_frames: self.block_frames,
+                    start_sec: 0.0,
+                    rate: entry.rate,
+                    loop_region: entry.loop_region,
+                };
+                MixedSource::new(
+                    entry.paths.clone(),
+                    track_offsets(&entry.mix),
+                    entry.gains.clone(),
+                    entry.automation.clone(),
+                    self.meters.clone(),
+                    self.tx.clone(),
+                    params,
+                )
+                .map(|mut src| { src.end_frames = entry.total_frames; src })
+                .ok() // a song that can't be opened ends the setlist
+            }
+            _ => None,
+        };
+        self.handoff.lock().next = next;
+    }
+
+    // The playing source has switched to the first queued song: make it the current one
+    fn advance_queue(&mut self) {
+        let Some(entry) = self.queue.pop_front() else { return };
+        self.paths = entry.paths;
+        self.song_dir = entry.song_dir;
+        self.total_frames = entry.total_frames;
+        self.mix = entry.mix;
+        self.rate = entry.rate;
+        self.loop_region = entry.loop_region;
+        self.gains = entry.gains;
+        self.automation = entry.automation;
+        self.pos_frames = 0;
+        self.play_started_at = Some(Instant::now());
+        self.meters.lock().reset(&self.paths);
+        self.arm_next();
+
+        if let Some(app) = APP_HANDLE.get() {
+            let _ = app.emit(SONG_CHANGED_EVENT, SongChangedEvent {
+                paths: self.paths.clone(),
+                song_dir: self.song_dir.clone(),
+            });
+        }
+    }
+
     fn kill_sink(&mut self) {
-        if let Some(sink) = self.sink.take() { sink.stop(); }
+        if let Some(out) = &self.output { out.clear(); }
+        self.has_source = false;
+        self.meters.lock().silence();
     }
 
     fn set_tracks_gain_by_path(&mut self, paths: Vec<String>, gain: f32, ramp_ms: Option<u32>) -> Result<(), String> {
         if self.paths.is_empty() { return Err("No audio loaded".into()); }
         let g_clamped = if gain.is_finite() { gain.max(0.0) } else { 0.0 };
+        for idx in self.indices_of(&paths) {
+            self.mix[idx].gain = g_clamped;
+            self.push_gain(idx, ramp_ms);
+        }
+        Ok(())
+    }
+
+    fn mute_tracks_by_path(&mut self, paths: Vec<String>, muted: bool, ramp_ms: Option<u32>) -> Result<(), String> {
+        if self.paths.is_empty() { return Err("No audio loaded".into()); }
+        for idx in self.indices_of(&paths) {
+            self.mix[idx].muted = muted;
+            self.push_gain(idx, ramp_ms);
+        }
+        Ok(())
+    }
+
+    fn set_tracks_pan_by_path(&mut self, paths: Vec<String>, pan: f32) -> Result<(), String> {
+        if self.paths.is_empty() { return Err("No audio loaded".into()); }
+        let pan = if pan.is_finite() { pan.clamp(-1.0, 1.0) } else { 0.0 };
+        for idx in self.indices_of(&paths) {
+            self.mix[idx].pan = pan;
+            self.push_gain(idx, None);
+        }
+        Ok(())
+    }
+
+    // Offsets are fixed per mix source, so changing them rebuilds it at the current position
+    fn set_tracks_offset_by_path(&mut self, paths: Vec<String>, offset_ms: f32) -> Result<(), String> {
+        if self.paths.is_empty() { return Err("No audio loaded".into()); }
+        let offset_ms = clamp_offset_ms(offset_ms);
+        let idxs = self.indices_of(&paths);
+        if idxs.iter().all(|&i| self.mix[i].offset_ms == offset_ms) { return Ok(()); }
+        self.sync_position();
+        for idx in idxs {
+            self.mix[idx].offset_ms = offset_ms;
+        }
+        self.restart_stream()
+    }
+
+    fn set_tracks_automation_by_path(&mut self, paths: Vec<String>, points: Vec<AutomationPoint>) -> Result<(), String> {
+        if self.paths.is_empty() { return Err("No audio loaded".into()); }
+        let points = sanitize_automation(points)?;
+        for idx in self.indices_of(&paths) {
+            self.mix[idx].automation = points.clone();
+        }
+        self.push_automation();
+        Ok(())
+    }
+
+    // Publishes all envelopes to the mix source (applied from its next block)
+    fn push_automation(&mut self) {
+        let mut a = self.automation.lock();
+        a.tracks = self.mix.iter().map(|m| m.automation.clone()).collect();
+        a.gen = a.gen.wrapping_add(1);
+    }
+
+    // Varispeed: the pitch follows the speed
+    fn set_playback_rate(&mut self, rate: f32) -> Result<(), String> {
+        if !rate.is_finite() || rate <= 0.0 { return Err(format!("Invalid playback rate {rate}")); }
+        let rate = rate.clamp(0.25, 2.0);
+        if rate != self.rate {
+            self.sync_position();
+            self.rate = rate;
+            self.restart_stream()?;
+        }
+        Ok(())
+    }
+
+    fn set_loop_region(&mut self, region: Option<LoopRegion>) -> Result<(), String> {
+        if let Some(r) = region {
+            if !r.start_secs.is_finite() || !r.end_secs.is_finite() || r.start_secs < 0.0 || r.end_secs <= r.start_secs {
+                return Err(format!("Invalid loop region {:.3}..{:.3}", r.start_secs, r.end_secs));
+            }
+        }
+        if region != self.loop_region {
+            self.sync_position();
+            self.loop_region = region;
+            self.restart_stream()?;
+        }
+        Ok(())
+    }
+
+    fn mix_settings(&self) -> MixSettings {
+        MixSettings {
+            tracks: self.mix.clone(),
+            playback_rate: self.rate,
+            loop_region: self.loop_region,
+        }
+    }
+
+    // With `song_dir`, saved track paths are relative to it (see `track_key`); otherwise full paths
+    fn apply_mix(&mut self, settings: MixSettings, song_dir: Option<&Path>) -> Result<(), String> {
+        if self.paths.is_empty() { return Err("No audio loaded".into()); }
+        let offsets_before = track_offsets(&self.mix);
+        for idx in merge_mix(&mut self.mix, &settings, song_dir)? {
+            self.push_gain(idx, Some(0));
+        }
+        self.push_automation();
+        if track_offsets(&self.mix) != offsets_before {
+            self.sync_position();
+            self.restart_stream()?;
+        }
+        self.set_playback_rate(settings.playback_rate)?;
+        self.set_loop_region(settings.loop_region)
+    }
+
+    fn indices_of(&self, paths: &[String]) -> Vec<usize> {
+        // paths not found are ignored
+        paths.iter()
+            .filter_map(|p| self.paths.iter().position(|pp| pp == p))
+            .collect()
+    }
+
+    // Publishes the mix of track `idx` to the mix source
+    fn push_gain(&mut self, idx: usize, ramp_ms: Option<u32>) {
         let ramp_ms = ramp_ms.unwrap_or(10);
         let ramp_frames = ((ramp_ms as u64 * self.out_sr as u64) / 1000) as usize;
-
+        let m = &self.mix[idx];
         let mut gains = self.gains.lock();
-        for p in &paths {
-            if let Some(idx) = self.paths.iter().position(|pp| pp == p) {
-                let mut gc = gains[idx];
-                gc.target = g_clamped;
-                gc.ramp_frames = ramp_frames;
-                gc.gen = gc.gen.wrapping_add(1);
-                gains[idx] = gc;
-            } else {
-                // ignore paths not found
+        let mut gc = gains[idx];
+        gc.target = if m.muted { 0.0 } else { m.gain };
+        gc.ramp_frames = ramp_frames;
+        gc.pan = m.pan;
+        gc.gen = gc.gen.wrapping_add(1);
+        gains[idx] = gc;
+    }
+
+    // Folds the time played so far into `pos_frames`, before changing rate or loop
+    fn sync_position(&mut self) {
+        if self.state == PlayState::Playing {
+            if let Some(t0) = self.play_started_at {
+                self.pos_frames = advance_position(
+                    self.pos_frames,
+                    t0.elapsed().as_secs_f64(),
+                    self.out_sr,
+                    self.rate as f64,
+                    self.loop_frames(),
+                ).round() as usize;
+                self.play_started_at = Some(Instant::now());
             }
         }
+    }
+
+    // Rate and loop are fixed per mix source: rebuild it at the current position
+    fn restart_stream(&mut self) -> Result<(), String> {
+        match self.state {
+            PlayState::Playing => {
+                let start_sec = self.pos_frames as f64 / self.out_sr as f64;
+                self.spawn_stream_from_time(start_sec)?;
+                self.play_started_at = Some(Instant::now());
+            }
+            // a paused source would resume with the old settings
+            PlayState::Paused => self.kill_sink(),
+            PlayState::Stopped => {}
+        }
         Ok(())
     }
 }
 
+fn default_mix(paths: &[String]) -> Vec<TrackMix> {
+    paths.iter()
+        .map(|p| TrackMix { path: p.clone(), gain: 1.0, muted: false, pan: 0.0, offset_ms: 0.0, automation: Vec::new() })
+        .collect()
+}
+
+// Copies the track settings of `settings` into `mix` (tracks matched by path, see `apply_mix`).
+// Returns the indices of the tracks that changed.
+fn merge_mix(mix: &mut [TrackMix], settings: &MixSettings, song_dir: Option<&Path>) -> Result<Vec<usize>, String> {
+    let mut changed = Vec::new();
+    for t in &settings.tracks {
+        let idx = match song_dir {
+            Some(dir) => mix.iter().position(|m| track_key(&m.path, dir) == t.path)
+                .or_else(|| mix.iter().position(|m| file_name_of(&m.path) == file_name_of(&t.path))),
+            None => mix.iter().position(|m| m.path == t.path),
+        };
+        let Some(idx) = idx else { continue }; // ignore tracks not loaded
+        let m = &mut mix[idx];
+        m.gain = if t.gain.is_finite() { t.gain.max(0.0) } else { 1.0 };
+        m.muted = t.muted;
+        m.pan = if t.pan.is_finite() { t.pan.clamp(-1.0, 1.0) } else { 0.0 };
+        m.offset_ms = clamp_offset_ms(t.offset_ms);
+        m.automation = sanitize_automation(t.automation.clone())?;
+        changed.push(idx);
+    }
+    Ok(changed)
+}
+
+fn track_offsets(mix: &[TrackMix]) -> Vec<f64> {
+    mix.iter().map(|m| m.offset_ms as f64 / 1000.0).collect()
+}
+
+fn duration_to_frames(duration_secs: Option<f64>, sr: u32) -> usize {
+    match duration_secs {
+        Some(d) if d.is_finite() && d > 0.0 => (d * sr as f64).round() as usize,
+        _ => 0,
+    }
+}
+
+impl SongEntry {
+    fn describe(&self, sr: u32) -> QueuedSong {
+        QueuedSong {
+            paths: self.paths.clone(),
+            song_dir: self.song_dir.clone(),
+            duration_secs: (self.total_frames > 0).then(|| self.total_frames as f64 / sr as f64),
+        }
+    }
+}
+
+fn clamp_offset_ms(offset_ms: f32) -> f32 {
+    if offset_ms.is_finite() { offset_ms.clamp(-MAX_TRACK_OFFSET_MS, MAX_TRACK_OFFSET_MS) } else { 0.0 }
+}
+
+// Validates points and sorts them by time
+fn sanitize_automation(mut points: Vec<AutomationPoint>) -> Result<Vec<AutomationPoint>, String> {
+    for p in &points {
+        if !p.time_secs.is_finite() || p.time_secs < 0.0 || !p.gain.is_finite() || p.gain < 0.0 {
+            return Err(format!("Invalid automation point {:.3}s -> {}", p.time_secs, p.gain));
+        }
+    }
+    points.sort_by(|a, b| a.time_secs.total_cmp(&b.time_secs));
+    Ok(points)
+}
+
+// Envelope value at `t` seconds (`points` sorted by time, not empty)
+fn envelope_gain(points: &[AutomationPoint], t: f64) -> f32 {
+    let idx = points.partition_point(|p| p.time_secs <= t);
+    if idx == 0 { return points[0].gain; }
+    if idx == points.len() { return points[idx - 1].gain; }
+    let (a, b) = (points[idx - 1], points[idx]);
+    let span = b.time_secs - a.time_secs;
+    if span <= 0.0 { return b.gain; }
+    let alpha = ((t - a.time_secs) / span) as f32;
+    a.gain + (b.gain - a.gain) * alpha
+}
+
+fn read_song_mix(song_dir: &Path) -> Result<Option<MixSettings>, String> {
+    let manifest = song_manifest::read_manifest(song_dir)
+        .map_err(|e| format!("Failed to read manifest in {}: {e}", song_dir.display()))?;
+    match manifest.and_then(|mut m| m.remove(MANIFEST_MIX_KEY)) {
+        Some(v) => serde_json::from_value(v)
+            .map(Some)
+            .map_err(|e| format!("Invalid mix preset in {}: {e}", song_dir.display())),
+        None => Ok(None),
+    }
+}
+
+// Saved presets refer to tracks relative to the song folder, so they survive moved libraries and cache copies
+fn track_key(path: &str, song_dir: &Path) -> String {
+    match Path::new(path).strip_prefix(song_dir) {
+        Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+        Err(_) => file_name_of(path).to_string(),
+    }
+}
+
+fn file_name_of(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+// Song position (frames) after `elapsed_secs` of playback started at `base`, wrapping at the loop end
+// the same way the mix source does (only when playback started before the loop end)
+fn advance_position(base: usize, elapsed_secs: f64, sr: u32, rate: f64, loop_frames: Option<(usize, usize)>) -> f64 {
+    let pos = base as f64 + elapsed_secs * sr as f64 * rate;
+    match loop_frames {
+        Some((ls, le)) if le > ls && base < le && pos >= le as f64 => {
+            ls as f64 + (pos - le as f64) % (le - ls) as f64
+        }
+        _ => pos,
+    }
+}
+
 // ---------- Streaming mix source with per-track gain ----------
 
 struct MixedSource {
     tracks: Vec<TrackStream>,
+    loop_start: Option<std::sync::Arc<LoopStart>>, // set with `loop_frames`
+    events: Sender<AudioCmd>,
     out_ch: u16,
     out_sr: u32,
     block_frames: usize,
+    rate: f32,
+    song_pos: f64,                      // song position of the next block (frames at out_sr)
+    loop_frames: Option<(f64, f64)>,    // only set if playback started before the loop end
+    end_frames: usize,                  // song length if known (0 = unknown), for crossfades
+    metering: bool,                     // false while fading out, so meters follow the incoming song
     buf: Vec<f32>,
     buf_pos: usize,
     finished: bool,
@@ -384,56 +1338,98 @@ struct MixedSource {
     // shared gain control
     gains: std::sync::Arc<Mutex<Vec<GainCmd>>>,
 
+    // shared level meters and the per-block levels computed before publishing them
+    meters: ArcMeters,
+    block_levels: Vec<LevelMeter>,
+
+    // shared automation and the local copy of the envelopes
+    automation: ArcAutomation,
+    automation_gen: u64,
+    envelopes: Vec<Vec<AutomationPoint>>,
+    env_buf: Vec<f32>, // envelope gain per frame of the current block
+
     // local state per track for smooth ramps
     curr_gain: Vec<f32>,
     target_gain: Vec<f32>,
     ramp_remaining: Vec<usize>,
     last_gen: Vec<u64>,
+    pan: Vec<f32>,
     scratch: Vec<f32>, // temporary buffer per track to render before mixing
 }
 
 impl MixedSource {
     fn new(
         paths: Vec<String>,
+        offsets: Vec<f64>,
         gains: std::sync::Arc<Mutex<Vec<GainCmd>>>,
-        out_sr: u32,
-        out_ch: u16,
-        block_frames: usize,
-        start_sec: f64,
+        automation: ArcAutomation,
+        meters: ArcMeters,
+        events: Sender<AudioCmd>,
+        params: MixParams,
     ) -> Result<Self, String> {
-        let mut tracks = Vec::with_capacity(paths.len());
-
-        for p in paths {
-            let path = Path::new(&p);
-            let file = File::open(path).map_err(|e| format!("Could not open {p}: {e}"))?;
-            let decoder = Decoder::new(BufReader::new(file))
-                .map_err(|e| format!("Could not decode {p}: {e}"))?;
-
-            let in_ch = decoder.channels() as usize;
-            let in_sr = decoder.sample_rate();
-
-            let src = decoder.convert_samples::<f32>();
-            let mut trk = TrackStream::new(Box::new(src), in_ch, in_sr, out_ch as usize, out_sr);
-            trk.skip_to_seconds(start_sec);
+        let MixParams { out_sr, out_ch, block_frames, start_sec, rate, loop_region } = params;
+        let tracks = open_tracks(&paths, &offsets, out_sr, out_ch, rate, start_sec)?;
+
+        let song_pos = start_sec.max(0.0) * out_sr as f64;
+        let loop_frames = loop_region
+            .map(|r| (r.start_secs * out_sr as f64, r.end_secs * out_sr as f64))
+            .filter(|&(ls, le)| le > ls && song_pos < le);
+        let loop_start = loop_frames.map(|(ls, _)| {
+            let start = LoopStart {
+                paths,
+                offsets,
+                out_sr,
+                out_ch,
+                rate,
+                start_sec: ls / out_sr as f64,
+                ready: Mutex::new(None),
+            };
+            start.prepare();
+            std::sync::Arc::new(start)
+        });
 
-            tracks.push(trk);
+        // start from the current mix instead of ramping from unity
+        let n = tracks.len();
+        let mut curr_gain = vec![1.0; n];
+        let mut last_gen = vec![0; n];
+        let mut pan = vec![0.0; n];
+        {
+            let shared = gains.lock();
+            for (i, gc) in shared.iter().enumerate().take(n) {
+                curr_gain[i] = gc.target;
+                last_gen[i] = gc.gen;
+                pan[i] = gc.pan;
+            }
         }
 
-        let n = tracks.len();
         Ok(Self {
             tracks,
+            loop_start,
+            events,
             out_ch,
             out_sr,
             block_frames: block_frames.max(64),
+            rate,
+            song_pos,
+            loop_frames,
+            end_frames: 0,
+            metering: true,
             buf: Vec::new(),
             buf_pos: 0,
             finished: false,
             headroom: 0.8, // ~ -1.9 dB
             gains,
-            curr_gain: vec![1.0; n],
-            target_gain: vec![1.0; n],
+            meters,
+            block_levels: vec![LevelMeter::default(); n],
+            automation,
+            automation_gen: u64::MAX, // forces the first snapshot
+            envelopes: vec![Vec::new(); n],
+            env_buf: Vec::new(),
+            target_gain: curr_gain.clone(),
+            curr_gain,
             ramp_remaining: vec![0; n],
-            last_gen: vec![0; n],
+            last_gen,
+            pan,
             scratch: Vec::new(),
         })
     }
@@ -447,26 +1443,39 @@ impl MixedSource {
                 self.target_gain[i] = gc.target;
                 self.ramp_remaining[i] = gc.ramp_frames;
                 self.last_gen[i] = gc.gen;
+                if gc.ramp_frames == 0 { self.curr_gain[i] = gc.target; }
+            }
+            self.pan[i] = gc.pan;
+        }
+        drop(shared);
+
+        let a = self.automation.lock();
+        if a.gen != self.automation_gen {
+            for (i, env) in self.envelopes.iter_mut().enumerate() {
+                *env = a.tracks.get(i).cloned().unwrap_or_default();
             }
+            self.automation_gen = a.gen;
         }
     }
 
     fn fill_block(&mut self) {
         let ch = self.out_ch as usize;
-        let frames = self.block_frames;
+        let mut frames = self.block_frames;
+        // end the block exactly at the loop end
+        if let Some((_, le)) = self.loop_frames.filter(|&(_, le)| self.song_pos < le) {
+            let left = ((le - self.song_pos) / self.rate as f64).ceil().max(1.0) as usize;
+            frames = frames.min(left);
+        }
         let needed = frames * ch;
 
-        if self.buf.len() != needed {
-            self.buf.resize(needed, 0.0);
-        } else {
-            for x in &mut self.buf { *x = 0.0; }
-        }
+        self.buf.clear();
+        self.buf.resize(needed, 0.0);
 
         // snapshot of gains and ramps
         self.update_gains_from_shared();
 
         // scratch per track
-        if self.scratch.len() != needed { self.scratch.resize(needed, 0.0); }
+        self.scratch.resize(needed, 0.0);
 
         let mut active = 0usize;
 
@@ -475,12 +1484,28 @@ impl MixedSource {
             self.scratch.fill(0.0);
             if self.tracks[i].render_block(&mut self.scratch, frames) { active += 1; }
 
+            // gain automation, evaluated at the song time of every frame
+            if !self.envelopes[i].is_empty() {
+                self.env_buf.clear();
+                let sr = self.out_sr as f64;
+                for f in 0..frames {
+                    let t = (self.song_pos + f as f64 * self.rate as f64) / sr;
+                    self.env_buf.push(envelope_gain(&self.envelopes[i], t));
+                }
+                for (s, x) in self.scratch.iter_mut().enumerate() {
+                    *x *= self.env_buf[s / ch];
+                }
+            }
+
             // ramp within block (if applicable)
             let mut cg = self.curr_gain[i];
             let tg = self.target_gain[i];
             let mut rem = self.ramp_remaining[i];
+            let (pan_l, pan_r) = pan_gains(self.pan[i], ch);
 
             // mix scratch into buf applying gain (and headroom will be applied at the end)
+            let mut peak = 0.0f32;
+            let mut sum_sq = 0.0f32;
             if rem > 0 {
                 // linear ramp per sample
                 for s in 0..needed {
@@ -490,17 +1515,27 @@ impl MixedSource {
                         rem -= 1;
                         cg
                     } else { cg };
-                    self.buf[s] += self.scratch[s] * g;
+                    let p = if (s % ch) & 1 == 0 { pan_l } else { pan_r };
+                    let v = self.scratch[s] * g * p;
+                    self.buf[s] += v;
+                    peak = peak.max(v.abs());
+                    sum_sq += v * v;
                 }
             } else {
                 // no ramp
                 for s in 0..needed {
-                    self.buf[s] += self.scratch[s] * cg;
+                    let p = if (s % ch) & 1 == 0 { pan_l } else { pan_r };
+                    let v = self.scratch[s] * cg * p;
+                    self.buf[s] += v;
+                    peak = peak.max(v.abs());
+                    sum_sq += v * v;
                 }
             }
 
             self.curr_gain[i] = cg;
             self.ramp_remaining[i] = rem;
+            // track levels are post-fader, before master headroom
+            self.block_levels[i] = LevelMeter { peak, rms: (sum_sq / needed as f32).sqrt() };
         }
 
         // apply small headroom to avoid hard clipping
@@ -510,6 +1545,23 @@ impl MixedSource {
             }
         }
 
+        if self.metering { self.publish_levels(); }
+
+        self.song_pos += frames as f64 * self.rate as f64;
+        if let Some((ls, le)) = self.loop_frames {
+            if self.song_pos >= le - 0.5 {
+                // if the loop start isn't reopened yet, play on and wrap after the next block
+                if let Some(start) = &self.loop_start {
+                    if let Some(tracks) = start.ready.lock().take() {
+                        let spent = std::mem::replace(&mut self.tracks, tracks);
+                        self.song_pos = ls;
+                        active = active.max(1); // keep going even if the loop end was silent
+                        let _ = self.events.send(AudioCmd::LoopWrapped { spent, start: start.clone() });
+                    }
+                }
+            }
+        }
+
         self.buf_pos = 0;
         if active == 0 {
             // No tracks left with samples; mark as finished
@@ -519,6 +1571,64 @@ impl MixedSource {
     }
 }
 
+fn open_tracks(
+    paths: &[String],
+    offsets: &[f64],
+    out_sr: u32,
+    out_ch: u16,
+    rate: f32,
+    start_sec: f64,
+) -> Result<Vec<TrackStream>, String> {
+    let mut tracks = Vec::with_capacity(paths.len());
+
+    for (i, p) in paths.iter().enumerate() {
+        let decoder = AudioDecoder::open(p)?;
+
+        let in_ch = decoder.channels();
+        let in_sr = decoder.sample_rate();
+
+        let mut trk = TrackStream::new(decoder, in_ch, in_sr, out_ch as usize, out_sr, rate);
+        trk.seek_song_time(start_sec, offsets.get(i).copied().unwrap_or(0.0));
+
+        tracks.push(trk);
+    }
+    Ok(tracks)
+}
+
+// Balance law: the side opposite to the pan direction is attenuated, the other stays at unity
+fn pan_gains(pan: f32, out_ch: usize) -> (f32, f32) {
+    if out_ch < 2 || pan == 0.0 { return (1.0, 1.0); }
+    ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0))
+}
+
+impl MixedSource {
+    // Output frames left until the known song end (None if unknown or looping)
+    fn frames_left(&self) -> Option<f64> {
+        if self.end_frames == 0 || self.loop_frames.is_some() { return None; }
+        let buffered = (self.buf.len() - self.buf_pos) as f64 / self.out_ch as f64;
+        let pos = self.song_pos - buffered * self.rate as f64;
+        Some(((self.end_frames as f64 - pos) / self.rate as f64).max(0.0))
+    }
+
+    fn publish_levels(&mut self) {
+        let mut peak = 0.0f32;
+        let mut sum_sq = 0.0f32;
+        for &v in &self.buf {
+            peak = peak.max(v.abs());
+            sum_sq += v * v;
+        }
+        let rms = if self.buf.is_empty() { 0.0 } else { (sum_sq / self.buf.len() as f32).sqrt() };
+
+        let mut m = self.meters.lock();
+        m.master.peak = m.master.peak.max(peak);
+        m.master.rms = rms;
+        for (lv, blk) in m.tracks.iter_mut().zip(&self.block_levels) {
+            lv.peak = lv.peak.max(blk.peak);
+            lv.rms = blk.rms;
+        }
+    }
+}
+
 impl Iterator for MixedSource {
     type Item = f32;
     fn next(&mut self) -> Option<Self::Item> {
@@ -533,47 +1643,111 @@ impl Iterator for MixedSource {
     }
 }
 
-impl Source for MixedSource {
-    #[inline] fn current_frame_len(&self) -> Option<usize> { None }
-    #[inline] fn channels(&self) -> u16 { self.out_ch }
-    #[inline] fn sample_rate(&self) -> u32 { self.out_sr }
-    #[inline] fn total_duration(&self) -> Option<std::time::Duration> { None }
+// ---------- Setlist: switches to the queued song gaplessly or with a crossfade ----------
+
+struct SetlistSource {
+    current: MixedSource,
+    outgoing: Option<MixedSource>, // previous song while crossfading
+    xfade_len: usize,              // crossfade length in samples
+    xfade_pos: usize,
+    handoff: ArcHandoff,
+    events: Sender<AudioCmd>,
+    frame_sample: usize,           // channel of the next sample, to switch on frame boundaries
+}
+
+impl SetlistSource {
+    fn new(current: MixedSource, handoff: ArcHandoff, events: Sender<AudioCmd>) -> Self {
+        Self { current, outgoing: None, xfade_len: 0, xfade_pos: 0, handoff, events, frame_sample: 0 }
+    }
+
+    // Swaps in the queued song. With a crossfade the previous one keeps playing while it fades out.
+    fn switch(&mut self, crossfade_frames: usize) -> bool {
+        let Some(next) = self.handoff.lock().next.take() else { return false };
+        let mut prev = std::mem::replace(&mut self.current, next);
+        if crossfade_frames > 0 {
+            prev.metering = false;
+            self.outgoing = Some(prev);
+            self.xfade_len = crossfade_frames * self.current.out_ch as usize;
+            self.xfade_pos = 0;
+        }
+        let _ = self.events.send(AudioCmd::SongChanged);
+        true
+    }
+}
+
+impl Iterator for SetlistSource {
+    type Item = f32;
+    fn next(&mut self) -> Option<Self::Item> {
+        let ch = self.current.out_ch as usize;
+        if self.outgoing.is_none() && self.frame_sample == 0 {
+            let xfade = self.handoff.lock().crossfade_frames;
+            if xfade > 0 && self.current.frames_left().is_some_and(|left| left <= xfade as f64) {
+                self.switch(xfade);
+            }
+        }
+
+        let v = match self.current.next() {
+            Some(v) => v,
+            None => {
+                // gapless: the song ended before any crossfade started
+                if !self.switch(0) { return None; }
+                self.current.next()?
+            }
+        };
+        self.frame_sample = (self.frame_sample + 1) % ch;
+
+        match &mut self.outgoing {
+            Some(prev) => {
+                // equal-power crossfade
+                let t = self.xfade_pos as f32 / self.xfade_len as f32;
+                let angle = t * std::f32::consts::FRAC_PI_2;
+                let o = prev.next().unwrap_or(0.0);
+                self.xfade_pos += 1;
+                if self.xfade_pos >= self.xfade_len { self.outgoing = None; }
+                Some(v * angle.sin() + o * angle.cos())
+            }
+            None => Some(v),
+        }
+    }
 }
 
 // ---------- Track with incremental linear resampling ----------
 
 struct TrackStream {
-    src: Box<dyn Iterator<Item = f32> + Send>, // interleaved, already in f32
+    src: AudioDecoder, // interleaved f32, seekable
     in_ch: usize,
     in_sr: u32,
     out_ch: usize,
 
     // incremental resampling
-    ratio: f64,    // in_sr / out_sr
+    ratio: f64,    // in_sr / out_sr * playback rate
     phase: f64,    // [0,1)
     last: Vec<f32>,// previous frame (len = in_ch)
     next: Vec<f32>,// next frame (len = in_ch)
     at_end: bool,  // true if no more samples left in src
+    lead_in: f64,  // input frames of silence still to play before src (positive offsets)
 }
 
 impl TrackStream {
     fn new(
-        src: Box<dyn Iterator<Item = f32> + Send>,
+        src: AudioDecoder,
         in_ch: usize,
         in_sr: u32,
         out_ch: usize,
         out_sr: u32,
+        rate: f32,
     ) -> Self {
         let mut me = Self {
             src,
             in_ch,
             in_sr,
             out_ch,
-            ratio: in_sr as f64 / out_sr as f64,
+            ratio: in_sr as f64 / out_sr as f64 * rate as f64,
             phase: 0.0,
             last: vec![0.0; in_ch],
             next: vec![0.0; in_ch],
             at_end: false,
+            lead_in: 0.0,
         };
         // Prefill two initial frames
         me.last = me.read_frame();
@@ -581,16 +1755,25 @@ impl TrackStream {
         me
     }
 
+    // Positions the track at `song_secs` of song time. A positive `offset_secs` plays silence
+    // before the track, a negative one starts it further in.
+    fn seek_song_time(&mut self, song_secs: f64, offset_secs: f64) {
+        let src_secs = song_secs.max(0.0) - offset_secs;
+        if src_secs < 0.0 {
+            self.lead_in = -src_secs * self.in_sr as f64;
+        } else {
+            self.skip_to_seconds(src_secs);
+        }
+    }
+
     fn skip_to_seconds(&mut self, secs: f64) {
         if secs <= 0.0 { return; }
         let total_in_frames = (secs * self.in_sr as f64).floor() as usize;
         if total_in_frames == 0 { return; }
 
-        // we discard total_in_frames from src
-        let to_discard = total_in_frames * self.in_ch;
-        for _ in 0..to_discard {
-            if self.src.next().is_none() { self.at_end = true; break; }
-        }
+        // seek the decoder to the whole frame; it decodes through if the format can't seek
+        self.src.seek(total_in_frames as f64 / self.in_sr as f64);
+        self.at_end = false;
 
         // The fractional remainder enters as initial phase
         let frac = secs * self.in_sr as f64 - total_in_frames as f64;
@@ -609,7 +1792,7 @@ impl TrackStream {
                 Some(s) => frame[c] = s,
                 None => {
                     self.at_end = true;
-                    for k in c..self.in_ch { frame[k] = 0.0; }
+                    frame[c..].fill(0.0);
                     break;
                 }
             }
@@ -625,6 +1808,12 @@ impl TrackStream {
 
         // out must come with size frames*ch_out; we assume already initialized to 0
         for f in 0..frames {
+            // leading silence of a delayed track (out is already zeroed)
+            if self.lead_in > 0.0 {
+                self.lead_in -= self.ratio;
+                continue;
+            }
+
             // advance based on ratio
             while self.phase >= 1.0 && !self.at_end {
                 self.last = std::mem::take(&mut self.next);
@@ -679,3 +1868,59 @@ fn default_output_format() -> Result<(u32, u16), String> {
     let cfg = device.default_output_config().map_err(|e| format!("Could not get output config: {e}"))?;
     Ok((cfg.sample_rate().0, cfg.channels()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pt(time_secs: f64, gain: f32) -> AutomationPoint {
+        AutomationPoint { time_secs, gain }
+    }
+
+    #[test]
+    fn envelope_holds_the_end_gains() {
+        let points = [pt(1.0, 0.5), pt(3.0, 1.5)];
+        assert_eq!(envelope_gain(&points, 0.0), 0.5);
+        assert_eq!(envelope_gain(&points, 1.0), 0.5);
+        assert_eq!(envelope_gain(&points, 3.0), 1.5);
+        assert_eq!(envelope_gain(&points, 10.0), 1.5);
+    }
+
+    #[test]
+    fn envelope_interpolates_between_points() {
+        let points = [pt(0.0, 0.0), pt(2.0, 1.0), pt(4.0, 0.0)];
+        assert!((envelope_gain(&points, 1.0) - 0.5).abs() < 1e-6);
+        assert!((envelope_gain(&points, 2.0) - 1.0).abs() < 1e-6);
+        assert!((envelope_gain(&points, 3.5) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn envelope_jumps_at_coincident_points() {
+        let points = [pt(0.0, 1.0), pt(2.0, 1.0), pt(2.0, 0.0), pt(4.0, 0.0)];
+        assert_eq!(envelope_gain(&points, 2.0), 0.0);
+        assert!((envelope_gain(&points, 1.999) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn envelope_of_a_single_point_is_constant() {
+        let points = [pt(5.0, 0.8)];
+        assert_eq!(envelope_gain(&points, 0.0), 0.8);
+        assert_eq!(envelope_gain(&points, 9.0), 0.8);
+    }
+
+    #[test]
+    fn sanitize_sorts_points_by_time() {
+        let points = sanitize_automation(vec![pt(3.0, 0.0), pt(1.0, 1.0), pt(2.0, 0.5)]).unwrap();
+        let times: Vec<f64> = points.iter().map(|p| p.time_secs).collect();
+        assert_eq!(times, [1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn sanitize_rejects_invalid_points() {
+        assert!(sanitize_automation(vec![pt(-1.0, 1.0)]).is_err());
+        assert!(sanitize_automation(vec![pt(f64::NAN, 1.0)]).is_err());
+        assert!(sanitize_automation(vec![pt(1.0, -0.1)]).is_err());
+        assert!(sanitize_automation(vec![pt(1.0, f32::INFINITY)]).is_err());
+        assert!(sanitize_automation(Vec::new()).unwrap().is_empty());
+    }
+}
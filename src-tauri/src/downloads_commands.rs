@@ -1,14 +1,168 @@
-use crate::downloads_service::{DOWNLOADS, DownloadStatus};
+use crate::archive_extract::ExtractLimits;
+use crate::song_manifest::{self, SongManifest, SongMetadata};
+use crate::downloads_service::{DOWNLOADS, DownloadLimits, DownloadRequest, DownloadStatus, FinishedDownload, PublishedSong, RetryPolicy, SongUpdate};
 
 /// Starts a download (if content already exists or is already in progress, returns error).
+/// The archive can be a ZIP, tar.gz, tar.zst or 7z. `keep_archive` stores a ZIP in the song folder
+/// instead of extracting it.
+/// `expected_sha256` / `expected_size`, when the repository publishes them, are checked before installing.
+/// `repository` and `song` (the repository's metadata) are recorded in the song's `parasync.json`.
+/// A partial download left in `.tmp/<key>` (failed or interrupted by an app restart) is continued.
+/// On Android `dest_root` is an app folder and `saf_dest` the SAF folder the song is copied to.
 #[tauri::command]
-pub fn start_song_download(key: String, download_url: String, dest_root: String) -> Result<(), String> {
-    DOWNLOADS.start_song_download(key, download_url, dest_root)
+#[allow(clippy::too_many_arguments)]
+pub fn start_song_download(
+    key: String,
+    download_url: String,
+    dest_root: String,
+    keep_archive: Option<bool>,
+    expected_sha256: Option<String>,
+    expected_size: Option<u64>,
+    repository: Option<String>,
+    song: Option<SongMetadata>,
+    saf_dest: Option<String>,
+) -> Result<(), String> {
+    DOWNLOADS.start_song_download(DownloadRequest {
+        key,
+        download_url,
+        dest_root,
+        keep_archive: keep_archive.unwrap_or(false),
+        expected_sha256,
+        expected_size,
+        local_path: None,
+        repository,
+        song,
+        update: false,
+        saf_dest,
+    })
+}
+
+/// Downloads the new version of an installed song and swaps it in atomically (see `check_song_updates`).
+/// The saved mix and other user data in `parasync.json` are kept.
+#[tauri::command]
+pub fn start_song_update(
+    key: String,
+    download_url: String,
+    dest_root: String,
+    expected_sha256: Option<String>,
+    expected_size: Option<u64>,
+    repository: Option<String>,
+    song: Option<SongMetadata>,
+) -> Result<(), String> {
+    DOWNLOADS.start_song_update(DownloadRequest {
+        key,
+        download_url,
+        dest_root,
+        keep_archive: false,
+        expected_sha256,
+        expected_size,
+        local_path: None,
+        repository,
+        song,
+        update: true,
+        saf_dest: None,
+    })
+}
+
+/// Returns the installed songs of `dest_root` that differ from what the repository publishes now
+#[tauri::command]
+pub fn check_song_updates(dest_root: String, songs: Vec<PublishedSong>) -> Result<Vec<SongUpdate>, String> {
+    DOWNLOADS.check_song_updates(dest_root, songs)
+}
+
+/// Imports a local song archive (ZIP, tar.gz, tar.zst or 7z) into `dest_root`.
+/// Returns the key whose progress shows up in `downloads_status`.
+#[tauri::command]
+pub fn import_song_archive(path: String, dest_root: String, keep_archive: Option<bool>) -> Result<String, String> {
+    if !std::path::Path::new(&path).is_file() { return Err(format!("'{path}' is not a file")); }
+    DOWNLOADS.import_song(path, dest_root, keep_archive.unwrap_or(false))
+}
+
+/// Imports an unpacked song folder, or a folder holding several song folders, into `dest_root`.
+/// Returns the key whose progress shows up in `downloads_status`.
+#[tauri::command]
+pub fn import_song_folder(path: String, dest_root: String) -> Result<String, String> {
+    if !std::path::Path::new(&path).is_dir() { return Err(format!("'{path}' is not a folder")); }
+    DOWNLOADS.import_song(path, dest_root, false)
+}
+
+/// Reads the `parasync.json` of an installed song (provenance, archive hash, repository metadata),
+/// migrated to the current schema. `None` if the folder has no manifest.
+#[tauri::command]
+pub fn song_manifest(song_dir: String) -> Result<Option<SongManifest>, String> {
+    song_manifest::read_song_manifest(std::path::Path::new(&song_dir))
 }
 
 /// Returns the status of ALL active downloads (download progress and extraction flag).
-/// Already finished downloads do NOT appear here.
+/// Already finished downloads do NOT appear here, see `downloads_history`.
 #[tauri::command]
 pub fn downloads_status() -> Result<Vec<DownloadStatus>, String> {
     DOWNLOADS.downloads_status()
 }
+
+/// Cancels a download and deletes its `.tmp/<key>` data.
+#[tauri::command]
+pub fn cancel_song_download(key: String) -> Result<(), String> {
+    DOWNLOADS.cancel_song_download(key)
+}
+
+#[tauri::command]
+pub fn pause_song_download(key: String) -> Result<(), String> {
+    DOWNLOADS.pause_song_download(key)
+}
+
+#[tauri::command]
+pub fn resume_song_download(key: String) -> Result<(), String> {
+    DOWNLOADS.resume_song_download(key)
+}
+
+/// Retry policy for transient failures (attempts, initial and max backoff delay).
+#[tauri::command]
+pub fn set_download_retry_policy(policy: RetryPolicy) -> Result<(), String> {
+    DOWNLOADS.set_retry_policy(policy)
+}
+
+#[tauri::command]
+pub fn download_retry_policy() -> RetryPolicy {
+    DOWNLOADS.retry_policy()
+}
+
+/// Finished downloads (completed, failed with their error, or cancelled), newest first.
+#[tauri::command]
+pub fn downloads_history() -> Vec<FinishedDownload> {
+    DOWNLOADS.downloads_history()
+}
+
+/// Clears the history entry of `key`, or all of them.
+#[tauri::command]
+pub fn clear_downloads_history(key: Option<String>) {
+    DOWNLOADS.clear_downloads_history(key)
+}
+
+/// How many seconds finished downloads are kept in the history.
+#[tauri::command]
+pub fn set_downloads_history_retention(secs: u64) {
+    DOWNLOADS.set_history_retention(secs)
+}
+
+/// Concurrent downloads and bandwidth caps (bytes/s, global and per download); applies immediately.
+#[tauri::command]
+pub fn set_download_limits(limits: DownloadLimits) -> Result<(), String> {
+    DOWNLOADS.set_limits(limits)
+}
+
+#[tauri::command]
+pub fn download_limits() -> DownloadLimits {
+    DOWNLOADS.limits()
+}
+
+/// Limits protecting the disk from zip bombs and corrupt archives (entries, sizes, ratio, free space).
+#[tauri::command]
+pub fn set_extract_limits(limits: ExtractLimits) -> Result<(), String> {
+    DOWNLOADS.set_extract_limits(limits)
+}
+
+#[tauri::command]
+pub fn extract_limits() -> ExtractLimits {
+    DOWNLOADS.extract_limits()
+}
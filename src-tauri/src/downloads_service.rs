@@ -1,10 +1,24 @@
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
-use serde::Serialize;
-use serde_json::{json, Value};
-use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::{Duration, SystemTime},
+};
 use tauri::async_runtime::{spawn, spawn_blocking};
-use tokio::{io::AsyncWriteExt, sync::Semaphore};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, sync::{watch, OwnedSemaphorePermit, Semaphore}};
+
+use crate::archive_extract::{self, ArchiveFormat, ExtractLimits, ExtractStep};
+use crate::saf_service::SAF;
+use crate::song_archive::SONG_ARCHIVE_FILE_NAME;
+use crate::song_validate;
+use crate::song_manifest::{self, SongManifest, SongMetadata, SongSource, MANIFEST_VERSION};
 
 /// Public singleton service
 pub static DOWNLOADS: Lazy<DownloadsService> = Lazy::new(DownloadsService::new);
@@ -17,6 +31,106 @@ pub struct DownloadStatus {
     pub total_bytes: Option<u64>,    // Content-Length if available
     pub progress: f32,               // 0..=1 (download); 1.0 when download finishes
     pub extracting: bool,            // true while ZIP is being extracted
+    pub extraction: Option<ExtractionProgress>, // details while extracting
+    pub paused: bool,
+    pub attempt: u32,                // current request attempt, 1-based (0 = still queued)
+    pub validating: bool,            // true while checking the installed song is playable
+    pub warnings: Vec<String>,       // non-fatal problems found by the validation
+    pub retry_at_ms: Option<u64>,    // when the next attempt starts (ms since epoch) while waiting to retry
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExtractionProgress {
+    pub entries_done: u64,
+    pub entries_total: u64,
+    pub bytes_written: u64,
+    pub bytes_total: u64,            // uncompressed size of all entries
+    pub current_file: Option<String>,// entry being written
+}
+
+/// Final state of a download, kept in the history for a while after it leaves `downloads_status`
+#[derive(Debug, Clone, Serialize)]
+pub struct FinishedDownload {
+    pub key: String,
+    pub outcome: DownloadOutcome,
+    pub error: Option<String>,       // reason when failed
+    pub bytes_downloaded: u64,
+    pub total_bytes: Option<u64>,
+    pub attempts: u32,
+    pub finished_at_ms: u64,         // unix time in ms
+    pub warnings: Vec<String>,       // installed, but with these problems (missing cover...)
+}
+
+/// What the repository currently publishes for a song, to compare with its installed manifest.
+/// Field names match the frontend's `Song`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishedSong {
+    pub id: String,
+    pub download_sha256: Option<String>,
+    pub uploaded_at: Option<String>,
+}
+
+/// An installed song whose repository version differs from the installed one
+#[derive(Debug, Clone, Serialize)]
+pub struct SongUpdate {
+    pub id: String,
+    pub song_dirs: Vec<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Concurrency and bandwidth caps, adjustable while downloads run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadLimits {
+    pub max_concurrent: usize,
+    pub global_bytes_per_sec: Option<u64>,      // None = unlimited
+    pub per_download_bytes_per_sec: Option<u64>,// applies to each download separately
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        Self { max_concurrent: 2, global_bytes_per_sec: None, per_download_bytes_per_sec: None }
+    }
+}
+
+/// How transient failures (network errors, 408/429/5xx) are retried
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,    // total attempts including the first one
+    pub initial_delay_ms: u64,// delay before the first retry, doubled on each one
+    pub max_delay_ms: u64,    // backoff cap, and the longest Retry-After waited for
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { max_attempts: 5, initial_delay_ms: 1_000, max_delay_ms: 30_000 }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with jitter: a random delay between half and all of the capped step
+    fn backoff(&self, retry: u32) -> Duration {
+        let step = self.initial_delay_ms.saturating_mul(1u64 << retry.min(20)).min(self.max_delay_ms);
+        Duration::from_millis(step / 2 + fastrand::u64(0..=step / 2))
+    }
+
+    /// Wait before retry number `retry`: the server's Retry-After in full (retrying earlier would
+    /// only use up the attempts), or the backoff. None if the server asks for more than `max_delay_ms`.
+    fn delay(&self, retry: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        match retry_after {
+            Some(d) if d > Duration::from_millis(self.max_delay_ms) => None,
+            Some(d) => Some(d),
+            None => Some(self.backoff(retry)),
+        }
+    }
 }
 
 /// Public API (thin wrapper over the internal manager)
@@ -32,8 +146,163 @@ impl DownloadsService {
     }
 
     /// Starts a download+extraction (unique key = `key`). Fails if already exists or already in progress.
-    pub fn start_song_download(&self, key: String, download_url: String, dest_root: String) -> Result<(), String> {
-        self.inner.start(key, download_url, dest_root)
+    /// With `keep_archive`, the ZIP is stored as-is in the song folder instead of being extracted.
+    pub fn start_song_download(&self, mut req: DownloadRequest) -> Result<(), String> {
+        normalize_sha256(&mut req.expected_sha256)?;
+        self.inner.start(req)
+    }
+
+    /// Downloads a new version of an installed song and swaps it in place of the old folder(s).
+    /// User data stored in `parasync.json` (saved mix...) is carried over. A song kept compressed
+    /// stays compressed.
+    pub fn start_song_update(&self, mut req: DownloadRequest) -> Result<(), String> {
+        normalize_sha256(&mut req.expected_sha256)?;
+        let installed = find_song_dirs_with_id(Path::new(&req.dest_root), &req.key)
+            .map_err(|e| format!("Failed to scan destination: {e}"))?;
+        let Some(first) = installed.first() else {
+            return Err(format!("Song '{}' is not installed in {}", req.key, req.dest_root));
+        };
+        if let Ok(Some(manifest)) = song_manifest::read_song_manifest(first) {
+            req.keep_archive = manifest.archive.is_some();
+        }
+        req.update = true;
+        self.inner.start(req)
+    }
+
+    /// Compares the manifests of the songs installed in `dest_root` with what the repository
+    /// publishes now. A song needs an update when its archive hash changed or, without hashes,
+    /// when it was uploaded again. Songs installed before manifests recorded either are skipped.
+    pub fn check_song_updates(&self, dest_root: String, published: Vec<PublishedSong>) -> Result<Vec<SongUpdate>, String> {
+        let mut updates = Vec::new();
+        for song in published {
+            let dirs = find_song_dirs_with_id(Path::new(&dest_root), &song.id)
+                .map_err(|e| format!("Failed to scan destination: {e}"))?;
+            let Some(Ok(Some(manifest))) = dirs.first().map(|d| song_manifest::read_song_manifest(d)) else {
+                continue;
+            };
+            let installed_at = manifest.song.as_ref().and_then(|s| s.uploaded_at.as_deref());
+            let published_sha = song.download_sha256.as_deref().map(|h| h.trim().to_ascii_lowercase());
+            let reason = match (manifest.archive_sha256.as_deref(), published_sha.as_deref()) {
+                (Some(old), Some(new)) if old != new => Some("archive changed".to_string()),
+                (Some(_), Some(_)) => None,
+                _ => match (installed_at, song.uploaded_at.as_deref()) {
+                    (Some(old), Some(new)) if old != new => Some(format!("uploaded again on {new}")),
+                    _ => None,
+                },
+            };
+            if let Some(reason) = reason {
+                updates.push(SongUpdate {
+                    id: song.id,
+                    song_dirs: dirs.iter().map(|d| d.display().to_string()).collect(),
+                    reason,
+                });
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Imports a local archive (ZIP, tar.gz, tar.zst, 7z) or an unpacked song folder through the
+    /// same pipeline as downloads. Returns the key to follow in `downloads_status`: `local-` and a
+    /// short hash of the canonical path, so files of the same name in different folders don't collide.
+    pub fn import_song(&self, path: String, dest_root: String, keep_archive: bool) -> Result<String, String> {
+        let canonical = fs::canonicalize(&path).map_err(|e| format!("'{path}' can't be imported: {e}"))?;
+        let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+        let short: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+        let key = format!("local-{short}");
+        self.inner.start(DownloadRequest {
+            key: key.clone(),
+            download_url: String::new(),
+            dest_root,
+            keep_archive,
+            expected_sha256: None,
+            expected_size: None,
+            local_path: Some(path),
+            repository: None,
+            song: None,
+            update: false,
+            saf_dest: None,
+        })?;
+        Ok(key)
+    }
+
+    /// Restores the downloads that were queued, running or paused when the app closed, and keeps
+    /// `<app_data_dir>/downloads_queue.json` up to date from now on
+    pub fn restore_queue(&self, app_data_dir: PathBuf) {
+        self.inner.restore_queue(app_data_dir.join(QUEUE_FILE_NAME));
+    }
+
+    /// Aborts a download (queued, running or paused) and deletes its partial data
+    pub fn cancel_song_download(&self, key: String) -> Result<(), String> {
+        self.inner.control(&key, Control::Cancel)
+    }
+
+    /// Pauses a download; it keeps its place but stops transferring
+    pub fn pause_song_download(&self, key: String) -> Result<(), String> {
+        self.inner.control(&key, Control::Pause)
+    }
+
+    pub fn resume_song_download(&self, key: String) -> Result<(), String> {
+        self.inner.control(&key, Control::Run)
+    }
+
+    /// Applies to running downloads from their next failure on
+    pub fn set_retry_policy(&self, policy: RetryPolicy) -> Result<(), String> {
+        if policy.max_attempts == 0 { return Err("max_attempts must be at least 1".into()); }
+        *self.inner.retry.lock() = policy;
+        Ok(())
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry.lock().clone()
+    }
+
+    pub fn set_limits(&self, limits: DownloadLimits) -> Result<(), String> {
+        if limits.max_concurrent == 0 { return Err("max_concurrent must be at least 1".into()); }
+        if limits.global_bytes_per_sec == Some(0) || limits.per_download_bytes_per_sec == Some(0) {
+            return Err("bandwidth limits must be above 0 (use null for unlimited)".into());
+        }
+        self.inner.set_limits(limits);
+        Ok(())
+    }
+
+    pub fn limits(&self) -> DownloadLimits {
+        self.inner.limits.lock().clone()
+    }
+
+    /// Size, entry count, compression ratio and free space limits checked when extracting
+    pub fn set_extract_limits(&self, limits: ExtractLimits) -> Result<(), String> {
+        if limits.max_entries == 0 || limits.max_total_bytes == 0 || limits.max_entry_bytes == 0 || limits.max_ratio == 0 {
+            return Err("extraction limits must be above 0".into());
+        }
+        *self.inner.extract_limits.lock() = limits;
+        Ok(())
+    }
+
+    pub fn extract_limits(&self) -> ExtractLimits {
+        self.inner.extract_limits.lock().clone()
+    }
+
+    /// Recently finished downloads (completed, failed or cancelled), newest first
+    pub fn downloads_history(&self) -> Vec<FinishedDownload> {
+        let mut history = self.inner.history.lock();
+        history.prune();
+        history.entries.iter().rev().cloned().collect()
+    }
+
+    /// Forgets one entry, or the whole history when `key` is None
+    pub fn clear_downloads_history(&self, key: Option<String>) {
+        let mut history = self.inner.history.lock();
+        match key {
+            Some(key) => history.entries.retain(|e| e.key != key),
+            None => history.entries.clear(),
+        }
+    }
+
+    /// How long finished downloads stay in the history
+    pub fn set_history_retention(&self, secs: u64) {
+        let mut history = self.inner.history.lock();
+        history.retention = Duration::from_secs(secs);
+        history.prune();
     }
 
     /// Lists the status of ALL active downloads (does not include those that already finished)
@@ -48,6 +317,12 @@ struct Progress {
     bytes_downloaded: u64,
     total_bytes: Option<u64>,
     extracting: bool,
+    extraction: Option<ExtractionProgress>,
+    paused: bool,
+    attempt: u32,
+    validating: bool,
+    warnings: Vec<String>,
+    retry_at_ms: Option<u64>,
 }
 
 impl Progress {
@@ -62,26 +337,121 @@ impl Progress {
             total_bytes: self.total_bytes,
             progress,
             extracting: self.extracting,
+            extraction: self.extraction.clone(),
+            paused: self.paused,
+            attempt: self.attempt,
+            validating: self.validating,
+            warnings: self.warnings.clone(),
+            retry_at_ms: self.retry_at_ms,
         }
     }
 }
 
 type ProgressArc = Arc<Mutex<Progress>>;
 
+/// A download slot, given back to the limiter when dropped unless the limit was lowered meanwhile
+struct Slot {
+    permit: Option<OwnedSemaphorePermit>,
+    excess: Arc<Mutex<usize>>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        // given back under the lock, so `set_limits` never counts it both as free and as busy
+        let mut excess = self.excess.lock();
+        let Some(permit) = self.permit.take() else { return };
+        if *excess > 0 {
+            *excess -= 1;
+            permit.forget();
+        } else {
+            drop(permit);
+        }
+    }
+}
+
+/// Everything a task needs to run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRequest {
+    pub key: String,
+    pub download_url: String,
+    pub dest_root: String,
+    pub keep_archive: bool, // store the ZIP as-is instead of extracting it
+    #[serde(default)]
+    pub expected_sha256: Option<String>, // lowercase hex, checked before installing
+    #[serde(default)]
+    pub expected_size: Option<u64>,
+    /// Local archive or unpacked folder imported instead of downloading `download_url`
+    #[serde(default)]
+    pub local_path: Option<String>,
+    /// Provenance recorded in the song's `parasync.json`
+    #[serde(default)]
+    pub repository: Option<String>,
+    #[serde(default)]
+    pub song: Option<SongMetadata>,
+    /// Replace the installed song with the same id instead of refusing it
+    #[serde(default)]
+    pub update: bool,
+    /// Android: `dest_root` is an app folder staging the download; once installed, its songs are
+    /// copied to this SAF folder ("" = the songs root) and `dest_root` is removed
+    #[serde(default)]
+    pub saf_dest: Option<String>,
+}
+
+struct History {
+    entries: Vec<FinishedDownload>, // oldest first
+    retention: Duration,
+}
+
+impl History {
+    fn prune(&mut self) {
+        let oldest = now_ms().saturating_sub(self.retention.as_millis() as u64);
+        self.entries.retain(|e| e.finished_at_ms >= oldest);
+    }
+}
+
+/// Default time finished downloads stay in the history
+const DEFAULT_HISTORY_RETENTION: Duration = Duration::from_secs(30 * 60);
+
 struct TaskHandle {
+    req: DownloadRequest,
+    seq: u64, // start order, kept when the queue is saved
     progress: ProgressArc,
+    control: watch::Sender<Control>,
 }
 
-const MANIFEST_FILE_NAME: &str = "parasync.json";
+/// Under `.tmp/<key>` while updating: the new version, and the old folders during the swap
+const STAGED_DIR_NAME: &str = "staged";
+const REPLACED_DIR_NAME: &str = "replaced";
+const QUEUE_FILE_NAME: &str = "downloads_queue.json";
+
+/// Saved queue entry; running and queued downloads are both restored as queued
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedDownload {
+    #[serde(flatten)]
+    req: DownloadRequest,
+    paused: bool,
+}
 
 /// Internal manager state
 struct ManagerInner {
     /// Active downloads by `key`
     tasks: Mutex<HashMap<String, TaskHandle>>,
-    /// Fixed concurrency limit (2)
+    /// Concurrency limit (`limits.max_concurrent` slots)
     limiter: Arc<Semaphore>,
+    /// Busy slots to drop instead of giving back, after the limit was lowered
+    excess_slots: Arc<Mutex<usize>>,
+    limits: Mutex<DownloadLimits>,
+    /// Shared by all downloads for the global bandwidth cap
+    global_bucket: TokenBucket,
+    extract_limits: Mutex<ExtractLimits>,
     /// Reusable HTTP client
     client: reqwest::Client,
+    retry: Mutex<RetryPolicy>,
+    /// Terminal states of finished tasks
+    history: Mutex<History>,
+    /// Where the queue is saved (None until `restore_queue`)
+    queue_file: Mutex<Option<PathBuf>>,
+    next_seq: AtomicU64,
 }
 
 impl ManagerInner {
@@ -95,36 +465,60 @@ impl ManagerInner {
 
         Self {
             tasks: Mutex::new(HashMap::new()),
-            limiter: Arc::new(Semaphore::new(2)), // fixed concurrency = 2
+            limiter: Arc::new(Semaphore::new(DownloadLimits::default().max_concurrent)),
+            excess_slots: Arc::new(Mutex::new(0)),
+            limits: Mutex::new(DownloadLimits::default()),
+            global_bucket: TokenBucket::default(),
+            extract_limits: Mutex::new(ExtractLimits::default()),
             client,
+            retry: Mutex::new(RetryPolicy::default()),
+            history: Mutex::new(History { entries: Vec::new(), retention: DEFAULT_HISTORY_RETENTION }),
+            queue_file: Mutex::new(None),
+            next_seq: AtomicU64::new(0),
         }
     }
 
-    fn start(self: &Arc<Self>, key: String, download_url: String, dest_root: String) -> Result<(), String> {
+    fn start(self: &Arc<Self>, req: DownloadRequest) -> Result<(), String> {
+        self.start_with(req, Control::Run)?;
+        self.save_queue();
+        Ok(())
+    }
+
+    fn start_with(self: &Arc<Self>, req: DownloadRequest, initial: Control) -> Result<(), String> {
+        let DownloadRequest { key, download_url, dest_root, local_path, .. } = &req;
         if key.trim().is_empty() { return Err("empty key".into()); }
-        if download_url.trim().is_empty() { return Err("empty download_url".into()); }
+        if download_url.trim().is_empty() && local_path.is_none() { return Err("empty download_url".into()); }
         if dest_root.trim().is_empty() { return Err("empty dest_root".into()); }
 
         // 1) Reject if already downloaded: look for a manifest that already tracks this song id
-        if has_existing_song_with_id(Path::new(&dest_root), &key)
-            .map_err(|e| format!("Failed to scan destination: {e}"))? {
+        let installed = !find_song_dirs_with_id(Path::new(dest_root), key)
+            .map_err(|e| format!("Failed to scan destination: {e}"))?
+            .is_empty();
+        if installed && !req.update {
             return Err(format!("Song '{key}' is already downloaded in {}", dest_root));
         }
+        if !installed && req.update {
+            return Err(format!("Song '{key}' is not installed in {}", dest_root));
+        }
 
         // 2) Reject if already downloading
         {
             let mut tasks = self.tasks.lock();
-            if tasks.contains_key(&key) {
+            if tasks.contains_key(key) {
                 return Err(format!("A download is already in progress for '{key}'"));
             }
             // Insert handle with initial progress
             let progress = Arc::new(Mutex::new(Progress::default()));
-            tasks.insert(key.clone(), TaskHandle { progress: progress.clone() });
+            let (control_tx, control_rx) = watch::channel(initial);
+            let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+            tasks.insert(key.clone(), TaskHandle { req: req.clone(), seq, progress: progress.clone(), control: control_tx });
+            // a new attempt replaces what the history says about this key
+            self.history.lock().entries.retain(|e| &e.key != key);
 
-            // 3) Launch async task
+            // 3) Launch async task (it records its outcome in the history)
             let inner = Arc::clone(self);
             spawn(async move {
-                let _ = inner.run_task(key, download_url, dest_root, progress).await;
+                inner.run_task(req, progress, control_rx).await;
             });
         }
 
@@ -138,193 +532,790 @@ impl ManagerInner {
             .collect()
     }
 
-    /// Removes a task from the map (called when finishing or on error)
-    fn remove_task(&self, key: &str) {
+    /// Moves a task from the map to the history (called when finishing or on error)
+    fn finish_task(&self, key: &str, progress: &Progress, result: Result<(), String>) {
+        let outcome = match &result {
+            Ok(()) => DownloadOutcome::Completed,
+            Err(e) if e == CANCELLED => DownloadOutcome::Cancelled,
+            Err(_) => DownloadOutcome::Failed,
+        };
+        let entry = FinishedDownload {
+            key: key.to_string(),
+            outcome,
+            error: result.err().filter(|_| outcome == DownloadOutcome::Failed),
+            bytes_downloaded: progress.bytes_downloaded,
+            total_bytes: progress.total_bytes,
+            attempts: progress.attempt,
+            finished_at_ms: now_ms(),
+            warnings: progress.warnings.clone(),
+        };
+        // both locks held so the key is never missing from status and history at the same time
         let mut tasks = self.tasks.lock();
+        let mut history = self.history.lock();
         tasks.remove(key);
+        history.prune();
+        history.entries.push(entry);
+        drop((tasks, history));
+        self.save_queue();
+    }
+
+    /// Sends a control signal to an active (or queued) download
+    fn control(&self, key: &str, control: Control) -> Result<(), String> {
+        let tasks = self.tasks.lock();
+        let handle = tasks.get(key).ok_or_else(|| format!("No active download for '{key}'"))?;
+        handle.control.send_replace(control);
+        drop(tasks);
+        self.save_queue();
+        Ok(())
+    }
+
+    fn set_limits(&self, limits: DownloadLimits) {
+        let mut current = self.limits.lock();
+        let mut excess = self.excess_slots.lock();
+        let (old, new) = (current.max_concurrent, limits.max_concurrent);
+        if new > old {
+            // busy slots still due to go away are kept instead
+            let kept = (new - old).min(*excess);
+            *excess -= kept;
+            self.limiter.add_permits(new - old - kept);
+        } else if new < old {
+            // free slots go away now; busy ones as their downloads finish
+            *excess += (old - new) - self.limiter.forget_permits(old - new);
+        }
+        *current = limits;
+    }
+
+    /// Delay to respect the bandwidth caps after receiving `n` more bytes
+    fn throttle(&self, own: &TokenBucket, n: u64) -> Duration {
+        let (global, per_download) = {
+            let limits = self.limits.lock();
+            (limits.global_bytes_per_sec, limits.per_download_bytes_per_sec)
+        };
+        self.global_bucket.take(n, global).max(own.take(n, per_download))
+    }
+
+    fn restore_queue(self: &Arc<Self>, path: PathBuf) {
+        let saved: Vec<QueuedDownload> = fs::read(&path).ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        *self.queue_file.lock() = Some(path);
+
+        // leftovers of downloads that are no longer queued (extraction, staging...) are dropped
+        let roots: HashSet<&str> = saved.iter().map(|q| q.req.dest_root.as_str()).collect();
+        let keys: HashSet<&str> = saved.iter().map(|q| q.req.key.as_str()).collect();
+        for root in roots {
+            remove_orphan_tmp_dirs(Path::new(root), &keys);
+        }
+
+        for QueuedDownload { req, paused } in saved {
+            let tmp_dir = Path::new(&req.dest_root).join(".tmp").join(&req.key);
+            let key = req.key.clone();
+            let initial = if paused { Control::Pause } else { Control::Run };
+            // e.g. installed just before the app closed
+            if let Err(e) = self.start_with(req, initial) {
+                eprintln!("[downloads] could not restore '{key}': {e}");
+                let _ = fs::remove_dir_all(tmp_dir);
+            }
+        }
+        self.save_queue();
+    }
+
+    /// Writes the active downloads to the queue file (replaced atomically)
+    fn save_queue(&self) {
+        let Some(path) = self.queue_file.lock().clone() else { return };
+        let mut queued: Vec<(u64, QueuedDownload)> = self.tasks.lock().values()
+            .map(|h| (h.seq, QueuedDownload { req: h.req.clone(), paused: *h.control.borrow() == Control::Pause }))
+            .collect();
+        queued.sort_by_key(|(seq, _)| *seq);
+        let queued: Vec<QueuedDownload> = queued.into_iter().map(|(_, q)| q).collect();
+
+        let result = serde_json::to_vec_pretty(&queued)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                if let Some(dir) = path.parent() { fs::create_dir_all(dir).map_err(|e| e.to_string())?; }
+                let tmp = path.with_extension("json.tmp");
+                fs::write(&tmp, data).map_err(|e| e.to_string())?;
+                fs::rename(&tmp, &path).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            eprintln!("[downloads] could not save the queue to {}: {e}", path.display());
+        }
     }
 
     async fn run_task(
         self: Arc<Self>,
-        key: String,
-        download_url: String,
-        dest_root: String,
+        req: DownloadRequest,
         progress: ProgressArc,
-    ) -> Result<(), String> {
-        // Respect concurrency (2)
-        let permit = match self.limiter.clone().acquire_owned().await {
-            Ok(p) => p,
-            Err(e) => {
-                self.remove_task(&key);
-                return Err(format!("Could not acquire download slot: {e}"));
+        mut control: watch::Receiver<Control>,
+    ) {
+        // temp: dest_root/.tmp/<key>/
+        let tmp_dir = Path::new(&req.dest_root).join(".tmp").join(&req.key);
+        let mut result = self.download_and_install(&req, &tmp_dir, &progress, &mut control).await;
+        match &result {
+            // keep the partial download so starting the same key again continues it
+            Err(f) if f.resumable => { let _ = fs::remove_dir_all(tmp_dir.join("extract")); }
+            _ => { let _ = fs::remove_dir_all(&tmp_dir); }
+        }
+        // done here rather than by the caller so downloads restored after a restart get there too
+        if let Some(saf_dest) = &req.saf_dest {
+            if result.is_ok() {
+                result = copy_to_saf(&req.dest_root, saf_dest).await.map_err(TaskFailure::from);
             }
-        };
+            // a resumable failure keeps its partial download there, as above
+            if !result.as_ref().is_err_and(|f| f.resumable) {
+                let _ = fs::remove_dir_all(&req.dest_root);
+            }
+        }
+        if let Err(f) = &result {
+            eprintln!("[downloads] {} failed: {}", req.key, f.message);
+        }
+        let progress = progress.lock().clone();
+        self.finish_task(&req.key, &progress, result.map_err(|f| f.message));
+    }
 
-        // temp: dest_root/.tmp/<key>/
-        let tmp_dir = Path::new(&dest_root).join(".tmp").join(&key);
-        if let Err(e) = fs::create_dir_all(&tmp_dir) {
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Could not create temp dir '{}': {e}", tmp_dir.display()));
+    async fn download_and_install(
+        &self,
+        req: &DownloadRequest,
+        tmp_dir: &Path,
+        progress: &ProgressArc,
+        control: &mut watch::Receiver<Control>,
+    ) -> Result<(), TaskFailure> {
+        if !req.update {
+            return self.install_into(req, tmp_dir, Path::new(&req.dest_root), progress, control).await;
         }
-        let tmp_zip_part = tmp_dir.join("file.zip.part");
+        // --- UPDATE: install into ".tmp/<key>/staged", then swap with the installed folders ---
+        let staged = tmp_dir.join(STAGED_DIR_NAME);
+        let _ = fs::remove_dir_all(&staged);
+        fs::create_dir_all(&staged).map_err(|e| format!("Could not create staging dir: {e}"))?;
+        self.install_into(req, tmp_dir, &staged, progress, control).await?;
+        let dest_root = Path::new(&req.dest_root);
+        let old_dirs = find_song_dirs_with_id(dest_root, &req.key)
+            .map_err(|e| format!("Failed to scan destination: {e}"))?;
+        Ok(swap_in_update(&staged, &old_dirs, dest_root, &tmp_dir.join(REPLACED_DIR_NAME))?)
+    }
+
+    /// Downloads (or copies) the song and installs its folder(s) under `install_root`
+    async fn install_into(
+        &self,
+        req: &DownloadRequest,
+        tmp_dir: &Path,
+        install_root: &Path,
+        progress: &ProgressArc,
+        control: &mut watch::Receiver<Control>,
+    ) -> Result<(), TaskFailure> {
+        let DownloadRequest { key, keep_archive, .. } = req;
+        // Respect the concurrency limit; the slot is held until extraction is done
+        let mut permit = None;
+
+        fs::create_dir_all(tmp_dir)
+            .map_err(|e| format!("Could not create temp dir '{}': {e}", tmp_dir.display()))?;
+        let tmp_zip_part = tmp_dir.join(PART_FILE_NAME);
         let tmp_zip = tmp_dir.join("file.zip");
         let extract_root = tmp_dir.join("extract");
+        let resume_path = tmp_dir.join(RESUME_FILE_NAME);
 
-        // --- DOWNLOAD (streaming) ---
-        let resp = match self.client.get(&download_url).send().await {
-            Ok(r) => r,
-            Err(e) => {
-                let _ = fs::remove_dir_all(&tmp_dir);
-                self.remove_task(&key);
-                drop(permit);
-                return Err(format!("Failed to start download: {e}"));
+        // Hash of the bytes written so far
+        let mut hash = StreamHash::default();
+
+        // --- IMPORT from a local archive (copied, the user's file stays) or folder ---
+        if let Some(local) = req.local_path.as_deref().map(Path::new) {
+            permit = Some(self.acquire_slot(control).await?);
+            if local.is_dir() {
+                return self.install_folder(req, local, install_root, progress, control).await;
             }
-        };
-        if !resp.status().is_success() {
-            let code = resp.status();
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Server responded {code} for {download_url}"));
+            if !tmp_zip.exists() {
+                copy_local_archive(local, &tmp_zip_part, progress).await?;
+                tokio::fs::rename(&tmp_zip_part, &tmp_zip).await
+                    .map_err(|e| format!("Could not rename temporary ZIP: {e}"))?;
+            }
+        }
+        // --- DOWNLOAD (streaming, resumed from a previous partial file if any) ---
+        // A complete file.zip from an interrupted run is reused (and verified again).
+        else if !tmp_zip.exists() {
+            progress.lock().attempt = 1;
+            loop {
+                // a paused download gives its slot back while it waits
+                if *control.borrow() != Control::Run {
+                    permit = None;
+                    wait_while_paused(control, progress).await?;
+                }
+                if permit.is_none() {
+                    permit = Some(self.acquire_slot(control).await?);
+                }
+                let fetch = self.fetch_into_part(req, &tmp_zip_part, &resume_path, &mut hash, progress, control);
+                let failure = match fetch.await {
+                    Ok(Fetch::Complete) => break,
+                    Ok(Fetch::Paused) => continue,
+                    Err(f) => f,
+                };
+                // transient failure: back off without holding a slot, then continue from the part file
+                let attempt = progress.lock().attempt;
+                let policy = self.retry.lock().clone();
+                if !failure.resumable || attempt >= policy.max_attempts {
+                    return Err(failure);
+                }
+                let Some(delay) = policy.delay(attempt - 1, failure.retry_after) else {
+                    let asked = failure.retry_after.unwrap_or_default().as_secs();
+                    return Err(TaskFailure::resumable(format!(
+                        "{} (the server asked to retry after {asked}s)", failure.message
+                    )));
+                };
+                eprintln!("[downloads] {key}: attempt {attempt} failed ({}), retrying in {delay:?}", failure.message);
+                permit = None;
+                progress.lock().retry_at_ms = Some(now_ms() + delay.as_millis() as u64);
+                let waited = tokio::select! {
+                    _ = tokio::time::sleep(delay) => true,
+                    _ = wait_for_cancel(control) => false,
+                };
+                let mut p = progress.lock();
+                p.retry_at_ms = None;
+                if !waited {
+                    return Err(CANCELLED.into());
+                }
+                p.attempt += 1;
+            }
+            tokio::fs::rename(&tmp_zip_part, &tmp_zip).await
+                .map_err(|e| format!("Could not rename temporary ZIP: {e}"))?;
+            let _ = fs::remove_file(&resume_path);
+        }
+        if permit.is_none() {
+            permit = Some(self.acquire_slot(control).await?);
         }
+        let _permit = permit;
 
+        // --- VERIFY size and checksum before touching the library ---
+        let archive = verify_download(req, &tmp_zip, hash).await?;
+
+        // --- Song folders in the archive: one top-level folder, a flat song or a pack ---
+        let format = ArchiveFormat::detect(&tmp_zip)?;
+        let folders = plan_song_folders(&tmp_zip, format, key)?;
+        let final_dirs: Vec<PathBuf> = folders.iter().map(|f| install_root.join(&f.name)).collect();
+        if let Some(existing) = final_dirs.iter().find(|d| d.exists()) {
+            return Err(format!("Destination folder '{}' already exists", existing.display()).into());
+        }
+
+        // --- KEEP COMPRESSED: "<dest_root>/<song folder>/song.zip", tracks are read from it ---
+        // Only ZIPs can be read in place, and a pack can't share one archive between its songs:
+        // those are extracted instead.
+        if *keep_archive && format == ArchiveFormat::Zip && folders.len() == 1 {
+            let final_dir = &final_dirs[0];
+            let result = async {
+                store_song_archive(&tmp_zip, final_dir)?;
+                self.validate_songs(vec![(final_dir.clone(), folders[0].name.clone())], progress).await?;
+                Ok(write_installed_manifest(final_dir, req, Some(&archive), Some(SONG_ARCHIVE_FILE_NAME), false)?)
+            }.await;
+            if result.is_err() {
+                let _ = fs::remove_dir_all(final_dir);
+            }
+            return result;
+        }
+
+        // --- EXTRACTION ---
+        progress.lock().extracting = true;
+
+        fs::create_dir_all(&extract_root).map_err(|e| format!("Could not create extract dir: {e}"))?;
+
+        // Extract loose into extract_root
         {
-            let mut pg = progress.lock();
-            pg.total_bytes = resp.content_length();
+            let tmp_zip = tmp_zip.clone();
+            let extract_root = extract_root.clone();
+            let progress = progress.clone();
+            let limits = self.extract_limits.lock().clone();
+            spawn_blocking(move || extract_archive_to(&tmp_zip, format, &extract_root, &limits, &progress))
+                .await
+                .map_err(|join_err| format!("Internal extraction error: {join_err}"))?
+                .map_err(|e| format!("Error extracting {}: {e}", format.name()))?;
+        }
+        // extraction can't be interrupted; honour a cancel that came in meanwhile
+        if *control.borrow() == Control::Cancel {
+            return Err(CANCELLED.into());
         }
 
-        let mut out = match tokio::fs::File::create(&tmp_zip_part).await {
-            Ok(f) => f,
-            Err(e) => {
-                let _ = fs::remove_dir_all(&tmp_dir);
-                self.remove_task(&key);
-                drop(permit);
-                return Err(format!("Could not create temporary file: {e}"));
+        // Check every song is playable before any of them is installed
+        let _ = fs::remove_dir_all(extract_root.join("__MACOSX"));
+        let sources: Vec<PathBuf> = folders.iter()
+            .map(|folder| match &folder.source {
+                Some(dir) => extract_root.join(dir),
+                None => extract_root.clone(),
+            })
+            .collect();
+        self.validate_songs(sources.iter().cloned().zip(folders.iter().map(|f| f.name.clone())).collect(), progress).await?;
+
+        // Move each song folder to "<dest_root>/<name>"; a flat archive moves the extract root itself
+        let in_pack = final_dirs.len() > 1;
+        for (i, (extracted_src, final_dir)) in sources.iter().zip(&final_dirs).enumerate() {
+            let result = move_dir(extracted_src, final_dir)
+                .map_err(|e| format!("Failed to move extracted folder: {e}"))
+                .and_then(|_| write_installed_manifest(final_dir, req, Some(&archive), None, in_pack));
+            if let Err(e) = result {
+                // all songs of the archive or none
+                for done in &final_dirs[..=i] {
+                    let _ = fs::remove_dir_all(done);
+                }
+                return Err(e.into());
             }
+        }
+        Ok(())
+    }
+
+    /// Waits for a free slot under the concurrency limit, unless cancelled first
+    async fn acquire_slot(&self, control: &mut watch::Receiver<Control>) -> Result<Slot, TaskFailure> {
+        let permit = tokio::select! {
+            p = self.limiter.clone().acquire_owned() => p.map_err(|e| format!("Could not acquire download slot: {e}"))?,
+            _ = wait_for_cancel(control) => return Err(CANCELLED.into()),
         };
+        Ok(Slot { permit: Some(permit), excess: self.excess_slots.clone() })
+    }
 
-        let mut stream = resp.bytes_stream();
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(bytes) => {
-                    if let Err(e) = out.write_all(&bytes).await {
-                        let _ = fs::remove_dir_all(&tmp_dir);
-                        self.remove_task(&key);
-                        drop(permit);
-                        return Err(format!("Error writing file: {e}"));
-                    }
-                    let mut pg = progress.lock();
-                    pg.bytes_downloaded = pg.bytes_downloaded.saturating_add(bytes.len() as u64);
-                }
-                Err(e) => {
-                    let _ = fs::remove_dir_all(&tmp_dir);
-                    self.remove_task(&key);
-                    drop(permit);
-                    return Err(format!("Error receiving data: {e}"));
-                }
+    /// Copies an unpacked song folder (or each song of a folder of songs) into the library
+    async fn install_folder(
+        &self,
+        req: &DownloadRequest,
+        src: &Path,
+        install_root: &Path,
+        progress: &ProgressArc,
+        control: &mut watch::Receiver<Control>,
+    ) -> Result<(), TaskFailure> {
+        let songs = plan_folder_songs(src)?;
+        let copies: Vec<(PathBuf, PathBuf)> = songs.into_iter()
+            .map(|(dir, name)| (dir, install_root.join(name)))
+            .collect();
+        if let Some((_, existing)) = copies.iter().find(|(_, d)| d.exists()) {
+            return Err(format!("Destination folder '{}' already exists", existing.display()).into());
+        }
+
+        progress.lock().extracting = true;
+        let result = {
+            let copies = copies.clone();
+            let progress = progress.clone();
+            spawn_blocking(move || copy_song_folders(&copies, &progress))
+                .await
+                .map_err(|join_err| format!("Internal copy error: {join_err}"))?
+        };
+        let result = async {
+            result?;
+            // copying can't be interrupted; honour a cancel that came in meanwhile
+            if *control.borrow() == Control::Cancel { return Err(CANCELLED.into()); }
+            let songs = copies.iter()
+                .map(|(_, dst)| (dst.clone(), dst.file_name().unwrap_or_default().to_string_lossy().to_string()))
+                .collect();
+            self.validate_songs(songs, progress).await?;
+            let in_pack = copies.len() > 1;
+            Ok(copies.iter().try_for_each(|(_, dst)| write_installed_manifest(dst, req, None, None, in_pack))?)
+        }.await;
+        if result.is_err() {
+            for (_, dst) in &copies {
+                let _ = fs::remove_dir_all(dst);
             }
         }
+        result
+    }
 
-        if let Err(e) = out.flush().await {
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Could not flush to file: {e}"));
+    /// Validates song folders (path, name shown in messages). Any error fails the install;
+    /// warnings are kept in the progress and end up in the history.
+    async fn validate_songs(&self, songs: Vec<(PathBuf, String)>, progress: &ProgressArc) -> Result<(), TaskFailure> {
+        progress.lock().validating = true;
+        let labelled = songs.len() > 1;
+        let reports = spawn_blocking(move || {
+            songs.into_iter()
+                .map(|(dir, name)| (name, song_validate::validate_song_dir(&dir)))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|join_err| format!("Internal validation error: {join_err}"))?;
+        progress.lock().validating = false;
+
+        let label = |name: &str, problem: &str| if labelled { format!("{name}: {problem}") } else { problem.to_string() };
+        let mut errors = Vec::new();
+        for (name, report) in &reports {
+            errors.extend(report.errors.iter().map(|e| label(name, e)));
+            progress.lock().warnings.extend(report.warnings.iter().map(|w| label(name, w)));
         }
-        drop(out);
-        if let Err(e) = tokio::fs::rename(&tmp_zip_part, &tmp_zip).await {
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Could not rename temporary ZIP: {e}"));
+        if !errors.is_empty() {
+            return Err(format!("The song is not playable: {}", errors.join("; ")).into());
         }
+        Ok(())
+    }
 
-        // --- Determine original top-level folder name (ignore __MACOSX) ---
-        let original_root_dir = detect_zip_primary_top_level_dir(&tmp_zip)
-            .map_err(|e| {
-                // cleanup
-                let _ = fs::remove_dir_all(&tmp_dir);
-                e
-            })?;
+    /// One request for the rest of the file: continues `part` with a Range request when the saved
+    /// validators allow it, otherwise (re)downloads from the start. Returns early when paused.
+    async fn fetch_into_part(
+        &self,
+        req: &DownloadRequest,
+        part: &Path,
+        resume_path: &Path,
+        hash: &mut StreamHash,
+        progress: &ProgressArc,
+        control: &mut watch::Receiver<Control>,
+    ) -> Result<Fetch, TaskFailure> {
+        let url = req.download_url.as_str();
+        let saved = read_resume_info(resume_path).filter(|r| r.url == url);
+        let have = fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+        // weak ETags can't be used with If-Range
+        let validator = saved.as_ref().and_then(|r| {
+            r.etag.clone().filter(|e| !e.starts_with("W/")).or_else(|| r.last_modified.clone())
+        });
 
-        // Destination path keeps the original folder name from the archive
-        let final_dir = Path::new(&dest_root).join(&original_root_dir);
+        let mut request = self.client.get(url);
+        let mut resume_from = 0;
+        if let (Some(validator), true) = (validator, have > 0) {
+            request = request
+                .header(header::RANGE, format!("bytes={have}-"))
+                .header(header::IF_RANGE, validator);
+            resume_from = have;
+        }
 
-        if final_dir.exists() {
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Destination folder '{}' already exists", final_dir.display()));
+        let resp = tokio::select! {
+            r = request.send() => r.map_err(|e| TaskFailure::resumable(format!("Failed to start download: {e}")))?,
+            _ = wait_for_cancel(control) => return Err(CANCELLED.into()),
+        };
+        let status = resp.status();
+
+        let (offset, total) = if status == StatusCode::PARTIAL_CONTENT && resume_from > 0 {
+            let (start, total) = parse_content_range(resp.headers().get(header::CONTENT_RANGE))
+                .ok_or_else(|| format!("Invalid Content-Range from {url}"))?;
+            if start != resume_from {
+                // unusable range: drop the partial file so the next attempt starts over
+                let _ = fs::remove_file(resume_path);
+                return Err(TaskFailure::resumable(format!("Server resumed at byte {start} instead of {resume_from}")));
+            }
+            (resume_from, total.or_else(|| resp.content_length().map(|n| n + resume_from)))
+        } else if status == StatusCode::RANGE_NOT_SATISFIABLE
+            && saved.as_ref().and_then(|r| r.total_bytes) == Some(have)
+        {
+            // the partial file is already complete
+            return Ok(Fetch::Complete);
+        } else if status.is_success() {
+            // full body: the file changed or the server ignores ranges
+            (0, resp.content_length())
+        } else {
+            let message = format!("Server responded {status} for {url}");
+            let transient = status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS;
+            if !transient { return Err(message.into()); }
+            let mut failure = TaskFailure::resumable(message);
+            failure.retry_after = resp.headers().get(header::RETRY_AFTER).and_then(parse_retry_after);
+            return Err(failure);
+        };
+
+        if let (Some(expected), Some(total)) = (req.expected_size, total) {
+            if expected != total {
+                return Err(format!("Server reports {total} bytes for '{}', expected {expected}", req.key).into());
+            }
         }
+        hash.catch_up(part, offset).await?;
 
-        // --- EXTRACTION ---
+        if offset == 0 {
+            let info = ResumeInfo {
+                url: url.to_string(),
+                etag: header_string(&resp, header::ETAG),
+                last_modified: header_string(&resp, header::LAST_MODIFIED),
+                total_bytes: total,
+            };
+            write_resume_info(resume_path, &info)?;
+        }
         {
             let mut pg = progress.lock();
-            pg.extracting = true; // boolean only
+            pg.bytes_downloaded = offset;
+            pg.total_bytes = total;
         }
 
-        if let Err(e) = fs::create_dir_all(&extract_root) {
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Could not create extract dir: {e}"));
+        let mut out = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(part)
+            .await
+            .map_err(|e| format!("Could not create temporary file: {e}"))?;
+
+        let mut written = offset;
+        let mut paused = false;
+        let mut stream = resp.bytes_stream();
+        let bucket = TokenBucket::default();
+        let mut not_before = tokio::time::Instant::now(); // reading is delayed to stay under the caps
+        loop {
+            let chunk = tokio::select! {
+                chunk = async {
+                    tokio::time::sleep_until(not_before).await;
+                    stream.next().await
+                } => chunk,
+                _ = control.changed() => {
+                    match *control.borrow() {
+                        Control::Run => continue,
+                        Control::Cancel => return Err(CANCELLED.into()),
+                        // drop the connection; resuming sends a new Range request
+                        Control::Pause => { paused = true; break }
+                    }
+                }
+            };
+            let Some(chunk) = chunk else { break };
+            let bytes = chunk.map_err(|e| TaskFailure::resumable(format!("Error receiving data: {e}")))?;
+            out.write_all(&bytes).await.map_err(|e| format!("Error writing file: {e}"))?;
+            hash.update(&bytes);
+            written += bytes.len() as u64;
+            if let Some(expected) = req.expected_size.filter(|n| written > *n) {
+                return Err(format!("Download of '{}' is larger than the expected {expected} bytes", req.key).into());
+            }
+            progress.lock().bytes_downloaded = written;
+            not_before = tokio::time::Instant::now() + self.throttle(&bucket, bytes.len() as u64);
         }
+        out.flush().await.map_err(|e| format!("Could not flush to file: {e}"))?;
 
-        // Extract loose into extract_root
-        let unzip_res = {
-            let tmp_zip = tmp_zip.clone();
-            let extract_root = extract_root.clone();
-            spawn_blocking(move || unzip_zip_to(&tmp_zip, &extract_root))
-                .await
-                .map_err(|join_err| format!("Internal extraction error: {join_err}"))?
+        if paused {
+            return Ok(Fetch::Paused);
+        }
+        if total.is_some_and(|t| written < t) {
+            return Err(TaskFailure::resumable(format!("Connection closed after {written} bytes")));
+        }
+        Ok(Fetch::Complete)
+    }
+}
+
+/// SHA-256 of the first `len` bytes of the downloaded file
+#[derive(Default)]
+struct StreamHash {
+    hasher: Sha256,
+    len: u64,
+}
+
+impl StreamHash {
+    fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        self.len += bytes.len() as u64;
+    }
+
+    /// Makes the hash cover exactly the first `len` bytes of `path`, rereading the file when the
+    /// stream restarted or continues a part written by an earlier run
+    async fn catch_up(&mut self, path: &Path, len: u64) -> Result<(), String> {
+        if self.len == len { return Ok(()); }
+        *self = Self::default();
+        if len == 0 { return Ok(()); }
+        let file = tokio::fs::File::open(path).await
+            .map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+        let mut reader = file.take(len);
+        let mut buf = vec![0u8; 256 * 1024];
+        loop {
+            let n = reader.read(&mut buf).await.map_err(|e| format!("Could not read {}: {e}", path.display()))?;
+            if n == 0 { break; }
+            self.update(&buf[..n]);
+        }
+        if self.len != len {
+            return Err(format!("{} is shorter than {len} bytes", path.display()));
+        }
+        Ok(())
+    }
+
+    fn hex(self) -> String {
+        self.hasher.finalize().iter().map(|b| format!("{b:02x}")).collect()
+    }
+}
+
+/// Checks the finished archive against the expected size and SHA-256, if any.
+/// Returns the archive's actual SHA-256 and size.
+async fn verify_download(req: &DownloadRequest, zip: &Path, mut hash: StreamHash) -> Result<ArchiveDigest, String> {
+    let size = fs::metadata(zip).map_err(|e| format!("Could not read {}: {e}", zip.display()))?.len();
+    if let Some(expected) = req.expected_size {
+        if size != expected {
+            return Err(format!("Download of '{}' is {size} bytes, expected {expected}: the file is incomplete or was replaced", req.key));
+        }
+    }
+    hash.catch_up(zip, size).await?;
+    let actual = hash.hex();
+    if let Some(expected) = &req.expected_sha256 {
+        if &actual != expected {
+            return Err(format!("Checksum mismatch for '{}': expected SHA-256 {expected}, got {actual}. The file is corrupt or was modified", req.key));
+        }
+    }
+    Ok(ArchiveDigest { sha256: actual, size })
+}
+
+struct ArchiveDigest {
+    sha256: String,
+    size: u64,
+}
+
+/// Token bucket for a bandwidth cap. The rate is passed on each call so changes apply at once.
+#[derive(Default)]
+struct TokenBucket {
+    state: Mutex<Option<(f64, std::time::Instant)>>, // available bytes (negative = debt), last refill
+}
+
+impl TokenBucket {
+    /// Takes `n` bytes and returns how long to wait before reading more
+    fn take(&self, n: u64, rate: Option<u64>) -> Duration {
+        let mut state = self.state.lock();
+        let Some(rate) = rate else {
+            *state = None;
+            return Duration::ZERO;
         };
+        let rate = rate as f64;
+        let now = std::time::Instant::now();
+        let (available, last) = state.unwrap_or((0.0, now));
+        // up to one second of burst
+        let refilled = (available + now.duration_since(last).as_secs_f64() * rate).min(rate);
+        let left = refilled - n as f64;
+        *state = Some((left, now));
+        if left >= 0.0 { Duration::ZERO } else { Duration::from_secs_f64(-left / rate) }
+    }
+}
 
-        if let Err(e) = unzip_res {
-            let _ = fs::remove_file(&tmp_zip);
-            let _ = fs::remove_dir_all(&tmp_dir);
-            self.remove_task(&key);
-            drop(permit);
-            return Err(format!("Error extracting ZIP: {e}"));
-        }
-
-        // Move/rename "<extract_root>/<original_root_dir>" -> "<dest_root>/<key>-<original_root_dir>"
-        let extracted_src = extract_root.join(&original_root_dir);
-        match move_dir(&extracted_src, &final_dir) {
-            Ok(()) => {
-                if let Err(e) = write_parasync_manifest(&final_dir, &key) {
-                    let _ = fs::remove_file(&tmp_zip);
-                    let _ = fs::remove_dir_all(&final_dir);
-                    let _ = fs::remove_dir_all(&tmp_dir);
-                    self.remove_task(&key);
-                    drop(permit);
-                    return Err(e);
-                }
+/// Why a task stopped. Resumable failures (network errors, 408/429/5xx) are retried, and once
+/// retries run out they keep `.tmp/<key>` so starting the same download again continues it.
+struct TaskFailure {
+    message: String,
+    resumable: bool,
+    retry_after: Option<Duration>, // asked by the server
+}
 
-                // cleanup
-                let _ = fs::remove_file(&tmp_zip);
-                let _ = fs::remove_dir_all(&tmp_dir);
-                self.remove_task(&key);
-                drop(permit);
-                Ok(())
-            }
-            Err(e) => {
-                let _ = fs::remove_file(&tmp_zip);
-                let _ = fs::remove_dir_all(&tmp_dir);
-                self.remove_task(&key);
-                drop(permit);
-                Err(format!("Failed to move extracted folder: {e}"))
-            }
+impl TaskFailure {
+    fn resumable(message: String) -> Self {
+        Self { message, resumable: true, retry_after: None }
+    }
+}
+
+impl From<String> for TaskFailure {
+    fn from(message: String) -> Self {
+        Self { message, resumable: false, retry_after: None }
+    }
+}
+
+impl From<&str> for TaskFailure {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+enum Fetch {
+    Complete,
+    Paused,
+}
+
+const PART_FILE_NAME: &str = "file.zip.part";
+const RESUME_FILE_NAME: &str = "file.zip.part.json";
+
+/// Validators of the partial download, saved next to `file.zip.part`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ResumeInfo {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    total_bytes: Option<u64>,
+}
+
+fn read_resume_info(path: &Path) -> Option<ResumeInfo> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+fn write_resume_info(path: &Path, info: &ResumeInfo) -> Result<(), String> {
+    let data = serde_json::to_vec(info).map_err(|e| format!("Failed to serialize resume info: {e}"))?;
+    fs::write(path, data).map_err(|e| format!("Failed to write {}: {e}", path.display()))
+}
+
+fn header_string(resp: &reqwest::Response, name: header::HeaderName) -> Option<String> {
+    resp.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &header::HeaderValue) -> Option<Duration> {
+    let v = value.to_str().ok()?.trim();
+    if let Ok(secs) = v.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = httpdate::parse_http_date(v).ok()?;
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+/// Parses `bytes <start>-<end>/<total|*>` into the start offset and the total size
+fn parse_content_range(value: Option<&header::HeaderValue>) -> Option<(u64, Option<u64>)> {
+    let v = value?.to_str().ok()?.trim();
+    let rest = v.strip_prefix("bytes ")?;
+    let (range, total) = rest.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+const CANCELLED: &str = "Download cancelled";
+
+/// Pause/resume/cancel requests for one task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Run,
+    Pause,
+    Cancel,
+}
+
+/// Returns once the task is allowed to run; errors if it gets cancelled while paused
+async fn wait_while_paused(control: &mut watch::Receiver<Control>, progress: &ProgressArc) -> Result<(), String> {
+    loop {
+        let state = *control.borrow_and_update();
+        progress.lock().paused = state == Control::Pause;
+        match state {
+            Control::Run => return Ok(()),
+            Control::Cancel => return Err(CANCELLED.into()),
+            Control::Pause => {}
+        }
+        if control.changed().await.is_err() {
+            return Err(CANCELLED.into());
+        }
+    }
+}
+
+/// Returns once the task is cancelled. Watches a clone of `control`, so a Pause seen meanwhile is
+/// still reported as a change to the caller's receiver.
+async fn wait_for_cancel(control: &watch::Receiver<Control>) {
+    let mut control = control.clone();
+    loop {
+        if *control.borrow_and_update() == Control::Cancel { return; }
+        if control.changed().await.is_err() { return; }
+    }
+}
+
+/// Copies the songs installed in the app folder `staging` to the SAF folder `saf_dest`
+async fn copy_to_saf(staging: &str, saf_dest: &str) -> Result<(), String> {
+    // only the song folders: drop the emptied temp folder first
+    let _ = fs::remove_dir(Path::new(staging).join(".tmp"));
+    let (staging, saf_dest) = (staging.to_string(), saf_dest.to_string());
+    let copied = spawn_blocking(move || SAF.copy_appdir_to_saf(staging, saf_dest, true))
+        .await
+        .map_err(|e| format!("Join error: {e}"))??;
+    if !copied {
+        return Err("Could not copy the song to the songs folder, check the app file permissions".into());
+    }
+    Ok(())
+}
+
+/// Removes `<dest_root>/.tmp/<key>` folders whose key is not in `keep`, except the partial
+/// downloads kept by a failed task: starting the same key again still continues them
+fn remove_orphan_tmp_dirs(dest_root: &Path, keep: &HashSet<&str>) {
+    let Ok(rd) = fs::read_dir(dest_root.join(".tmp")) else { return };
+    for entry in rd.flatten() {
+        let name = entry.file_name();
+        if !keep.contains(name.to_string_lossy().as_ref()) && !has_partial_download(&entry.path()) {
+            let _ = fs::remove_dir_all(entry.path());
         }
     }
 }
 
-/// Checks if a manifest already exists for the requested song id.
-fn has_existing_song_with_id(dest_root: &Path, song_id: &str) -> io::Result<bool> {
+/// A resumable `file.zip.part` (with its validators) or a complete `file.zip` not installed yet
+fn has_partial_download(tmp_dir: &Path) -> bool {
+    tmp_dir.join(RESUME_FILE_NAME).is_file() || tmp_dir.join("file.zip").is_file()
+}
+
+/// Song folders whose manifest tracks the requested song id, or that were installed with it as
+/// a pack (several folders).
+fn find_song_dirs_with_id(dest_root: &Path, song_id: &str) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
     let rd = match fs::read_dir(dest_root) {
         Ok(rd) => rd,
         Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound { return Ok(false); }
+            if e.kind() == io::ErrorKind::NotFound { return Ok(found); }
             return Err(e);
         }
     };
@@ -348,112 +1339,352 @@ fn has_existing_song_with_id(dest_root: &Path, song_id: &str) -> io::Result<bool
             continue;
         }
 
-        if let Ok(Some(existing_id)) = read_manifest_song_id(&entry.path()) {
-            if existing_id == song_id {
-                return Ok(true);
+        if let Ok(Some(ids)) = read_manifest_song_ids(&entry.path()) {
+            if ids.iter().any(|id| id == song_id) {
+                found.push(entry.path());
             }
         }
     }
 
-    Ok(false)
+    found.sort();
+    Ok(found)
 }
 
-fn read_manifest_song_id(dir: &Path) -> io::Result<Option<String>> {
-    let manifest_path = dir.join(MANIFEST_FILE_NAME);
-    let contents = match fs::read_to_string(&manifest_path) {
-        Ok(contents) => contents,
-        Err(e) => {
-            if e.kind() == io::ErrorKind::NotFound { return Ok(None); }
-            return Err(e);
+/// Replaces the installed folders of a song with the staged new version.
+/// Every folder is renamed within `dest_root` (same filesystem), and a failure puts the old
+/// folders back. A single folder keeps its current name, so the song doesn't move in the library.
+fn swap_in_update(staged: &Path, old_dirs: &[PathBuf], dest_root: &Path, replaced: &Path) -> Result<(), String> {
+    let mut new_dirs: Vec<PathBuf> = fs::read_dir(staged)
+        .map_err(|e| format!("Could not read staging dir: {e}"))?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    new_dirs.sort();
+    let targets: Vec<PathBuf> = match (new_dirs.as_slice(), old_dirs) {
+        ([_], [old]) => vec![old.clone()],
+        _ => new_dirs.iter().map(|d| dest_root.join(d.file_name().unwrap_or_default())).collect(),
+    };
+    if let Some(taken) = targets.iter().find(|t| t.exists() && !old_dirs.contains(t)) {
+        return Err(format!("Destination folder '{}' already exists", taken.display()));
+    }
+
+    // user data (saved mix...) follows the folder of the same name, or the first old one
+    for (new_dir, target) in new_dirs.iter().zip(&targets) {
+        if let Some(old) = old_dirs.iter().find(|o| o == &target).or(old_dirs.first()) {
+            song_manifest::copy_user_fields(old, new_dir)?;
+        }
+    }
+
+    let _ = fs::remove_dir_all(replaced);
+    fs::create_dir_all(replaced).map_err(|e| format!("Could not create dir {}: {e}", replaced.display()))?;
+    let backups: Vec<PathBuf> = (0..old_dirs.len()).map(|i| replaced.join(i.to_string())).collect();
+    let restore = |moved_old: usize, moved_new: usize| {
+        for target in &targets[..moved_new] {
+            let _ = fs::remove_dir_all(target);
+        }
+        for (old, backup) in old_dirs.iter().zip(&backups).take(moved_old) {
+            let _ = fs::rename(backup, old);
         }
     };
+    for (i, (old, backup)) in old_dirs.iter().zip(&backups).enumerate() {
+        if let Err(e) = fs::rename(old, backup) {
+            restore(i, 0);
+            return Err(format!("Could not move {} aside: {e}", old.display()));
+        }
+    }
+    for (i, (new_dir, target)) in new_dirs.iter().zip(&targets).enumerate() {
+        if let Err(e) = fs::rename(new_dir, target) {
+            restore(old_dirs.len(), i);
+            return Err(format!("Could not move the new version to {}: {e}", target.display()));
+        }
+    }
+    let _ = fs::remove_dir_all(replaced);
+    Ok(())
+}
 
-    match serde_json::from_str::<Value>(&contents) {
-        Ok(value) => Ok(value
-            .get("id")
-            .and_then(|v| v.as_str())
-            .map(|id| id.to_string())),
-        Err(_) => Ok(None),
+/// Lowercases an expected SHA-256 and checks it is 64 hex digits
+fn normalize_sha256(hash: &mut Option<String>) -> Result<(), String> {
+    if let Some(hash) = hash {
+        *hash = hash.trim().to_ascii_lowercase();
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("Invalid SHA-256 '{hash}', expected 64 hex digits"));
+        }
     }
+    Ok(())
 }
 
-fn write_parasync_manifest(dir: &Path, song_id: &str) -> Result<(), String> {
-    let manifest = json!({ "id": song_id });
-    let data = serde_json::to_vec_pretty(&manifest)
-        .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
-    let manifest_path = dir.join(MANIFEST_FILE_NAME);
-    fs::write(&manifest_path, data)
-        .map_err(|e| format!("Failed to write manifest {}: {e}", manifest_path.display()))
+/// Song id and, for a song of a pack, the pack id from the manifest
+fn read_manifest_song_ids(dir: &Path) -> io::Result<Option<Vec<String>>> {
+    Ok(song_manifest::read_manifest(dir)?.map(|m| {
+        ["id", "pack"].iter()
+            .filter_map(|k| m.get(*k).and_then(|v| v.as_str()).map(|id| id.to_string()))
+            .collect()
+    }))
 }
 
-/// Extracts a ZIP to `dest_root` (loose). Zip-slip protected.
-/// Does NOT pre-create a dedicated final folder; it will mirror the ZIP structure under dest_root.
-fn unzip_zip_to(zip_path: &Path, dest_root: &Path) -> Result<(), String> {
-    let file = fs::File::open(zip_path).map_err(|e| format!("Could not open ZIP {}: {e}", zip_path.display()))?;
-    let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("Invalid ZIP: {e}"))?;
+/// Writes the manifest of a freshly installed song: id, provenance and repository metadata.
+/// `stored_archive` is the archive kept in the folder, when it isn't extracted. Each song of a
+/// pack gets its own id, `<key>/<folder name>`, and records the key as its pack.
+fn write_installed_manifest(
+    dir: &Path,
+    req: &DownloadRequest,
+    archive: Option<&ArchiveDigest>,
+    stored_archive: Option<&str>,
+    in_pack: bool,
+) -> Result<(), String> {
+    let non_empty = |s: &str| (!s.trim().is_empty()).then(|| s.to_string());
+    let id = match dir.file_name() {
+        Some(name) if in_pack => format!("{}/{}", req.key, name.to_string_lossy()),
+        _ => req.key.clone(),
+    };
+    song_manifest::write_song_manifest(dir, &SongManifest {
+        version: MANIFEST_VERSION,
+        id,
+        pack: in_pack.then(|| req.key.clone()),
+        source: Some(SongSource {
+            repository: req.repository.clone(),
+            download_url: non_empty(&req.download_url),
+            local_path: req.local_path.clone(),
+        }),
+        installed_at_ms: Some(now_ms()),
+        archive_sha256: archive.map(|a| a.sha256.clone()),
+        archive_size: archive.map(|a| a.size),
+        archive: stored_archive.map(str::to_string),
+        song: req.song.clone(),
+        other: Default::default(),
+    })
+}
 
-    for i in 0..zip.len() {
-        let mut entry = zip.by_index(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
+/// Extracts the archive to `dest_root` (loose), reporting progress in `progress.extraction`.
+/// Does NOT pre-create a dedicated final folder; it will mirror the archive structure under dest_root.
+fn extract_archive_to(
+    archive: &Path,
+    format: ArchiveFormat,
+    dest_root: &Path,
+    limits: &ExtractLimits,
+    progress: &ProgressArc,
+) -> Result<(), String> {
+    archive_extract::extract(archive, format, dest_root, limits, &mut |step| {
+        let mut pg = progress.lock();
+        if let ExtractStep::Started { entries, bytes } = step {
+            pg.extraction = Some(ExtractionProgress { entries_total: entries, bytes_total: bytes, ..Default::default() });
+            return;
+        }
+        let Some(x) = pg.extraction.as_mut() else { return };
+        match step {
+            ExtractStep::Entry(name) => x.current_file = Some(name.to_string()),
+            ExtractStep::Bytes(n) => x.bytes_written += n,
+            ExtractStep::EntryDone => x.entries_done += 1,
+            ExtractStep::Started { .. } => {}
+        }
+    })?;
+    if let Some(x) = progress.lock().extraction.as_mut() { x.current_file = None; }
+    Ok(())
+}
 
-        // zip-slip defense
-        let relpath = match entry.enclosed_name() {
-            Some(p) => p.to_owned(),
-            None => return Err(format!("Unsafe ZIP path in entry #{i}")),
-        };
+/// A song folder to create in the library
+#[derive(Debug)]
+struct SongFolder {
+    name: String,           // folder name in dest_root
+    source: Option<String>, // folder in the archive ("Pack/Song" for nested packs); None = the archive root (flat archive)
+}
 
-        let out_path = dest_root.join(relpath);
+/// Finds the songs in an archive (ignores "__MACOSX" and hidden files at each level):
+/// - a chart at the root, or no chart at all: one flat song, in a folder named after its chart title (or the key)
+/// - a single top-level folder with a chart of its own: that folder
+/// - a single top-level folder without one ("Pack/SongA", "Pack/SongB"): the same rules inside it
+/// - several top-level folders: every one holding a `.rlrr` chart is a song of the pack
+///
+/// Loose files next to song folders (a readme at the root...) are left out.
+fn plan_song_folders(archive: &Path, format: ArchiveFormat, key: &str) -> Result<Vec<SongFolder>, String> {
+    let entries = archive_extract::list_entries(archive, format)?;
 
-        if entry.name().ends_with('/') {
-            fs::create_dir_all(&out_path).map_err(|e| format!("Could not create dir {}: {e}", out_path.display()))?;
-        } else {
-            if let Some(parent) = out_path.parent() {
-                fs::create_dir_all(parent).map_err(|e| format!("Could not create dir {}: {e}", parent.display()))?;
+    let mut base: Vec<String> = Vec::new(); // folder the songs are looked for in
+    loop {
+        let mut tops: Vec<String> = Vec::new();
+        let mut song_tops: HashSet<String> = HashSet::new(); // holding a chart at any depth
+        let mut own_chart: HashSet<String> = HashSet::new(); // holding a chart directly
+        let mut files = false;
+        let mut flat_chart = false;
+        let mut first_chart: Option<&Path> = None;
+
+        for entry in &entries {
+            let rel = entry.path.as_path();
+            let parts: Vec<String> = rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+            if parts.len() <= base.len() || parts[..base.len()] != base[..] { continue; }
+            let top = &parts[base.len()];
+            // ignore macOS resource directory and dotfiles like .DS_Store
+            if top == "__MACOSX" || top.starts_with('.') { continue; }
+
+            let is_chart = !entry.is_dir && has_chart_extension(rel);
+            if is_chart && first_chart.is_none() { first_chart = Some(rel); }
+            let depth = parts.len() - base.len();
+            if depth == 1 && !entry.is_dir {
+                files = true;
+                flat_chart |= is_chart;
+                continue;
             }
-            let mut outfile = fs::File::create(&out_path)
-                .map_err(|e| format!("Could not create file {}: {e}", out_path.display()))?;
-            io::copy(&mut entry, &mut outfile)
-                .map_err(|e| format!("Error writing {}: {e}", out_path.display()))?;
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = entry.unix_mode() {
-                    let _ = fs::set_permissions(&out_path, fs::Permissions::from_mode(mode));
-                }
+            if is_chart {
+                song_tops.insert(top.clone());
+                if depth == 2 { own_chart.insert(top.clone()); }
             }
+            if !tops.contains(top) { tops.push(top.clone()); }
         }
+
+        // a flat song: its chart is right here (a chartless archive is kept as one song too)
+        let flat = flat_chart || (base.is_empty() && files && song_tops.is_empty());
+        if flat || (tops.len() > 1 && song_tops.is_empty()) {
+            let name = first_chart
+                .and_then(|chart| chart_title(archive, format, chart))
+                .map(|t| sanitize_folder_name(&t))
+                .filter(|t| !t.is_empty())
+                .unwrap_or_else(|| sanitize_folder_name(key));
+            if name.is_empty() { return Err(format!("No usable folder name for '{key}'")); }
+            let source = (!base.is_empty()).then(|| base.join("/"));
+            return Ok(vec![SongFolder { name, source }]);
+        }
+        if let [top] = tops.as_slice() {
+            if !own_chart.contains(top) && song_tops.contains(top) {
+                base.push(top.clone());
+                continue;
+            }
+        }
+        if tops.len() > 1 {
+            tops.retain(|t| song_tops.contains(t));
+        }
+        if tops.is_empty() {
+            return Err(format!("{} archive appears empty", format.name()));
+        }
+        return Ok(tops.into_iter()
+            .map(|t| {
+                let source = base.iter().chain([&t]).cloned().collect::<Vec<_>>().join("/");
+                SongFolder { name: t, source: Some(source) }
+            })
+            .collect());
     }
+}
 
-    Ok(())
+fn has_chart_extension(rel: &Path) -> bool {
+    rel.extension().is_some_and(|e| e.eq_ignore_ascii_case("rlrr"))
+}
+
+/// `recordingMetadata.title` of a `.rlrr` chart
+fn chart_title(archive: &Path, format: ArchiveFormat, chart: &Path) -> Option<String> {
+    let data = archive_extract::read_file(archive, format, chart).ok()?;
+    let chart: Value = serde_json::from_slice(&data).ok()?;
+    chart.get("recordingMetadata")?.get("title")?.as_str().map(|t| t.to_string())
 }
 
-/// Detects the primary top-level directory name inside the ZIP (ignores "__MACOSX").
-/// Returns its name (e.g., "MySongFolder") or error if not a single, clear folder.
-fn detect_zip_primary_top_level_dir(zip_path: &Path) -> Result<String, String> {
-    let file = fs::File::open(zip_path).map_err(|e| format!("Could not open ZIP {}: {e}", zip_path.display()))?;
-    let mut zip = zip::ZipArchive::new(file).map_err(|e| format!("Invalid ZIP: {e}"))?;
+/// Keeps a title usable as a folder name on every platform
+fn sanitize_folder_name(name: &str) -> String {
+    let cleaned: String = name.chars()
+        .map(|c| if c.is_control() || r#"/\:*?"<>|"#.contains(c) { '_' } else { c })
+        .collect();
+    cleaned.trim().trim_matches('.').trim().to_string()
+}
 
-    use std::collections::HashSet;
-    let mut tops: HashSet<String> = HashSet::new();
+/// Song folders of an unpacked import: the folder itself when it holds a `.rlrr` chart,
+/// otherwise each of its subfolders that does. Returns (source, folder name).
+fn plan_folder_songs(src: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let has_chart = |dir: &Path| -> bool {
+        fs::read_dir(dir).map(|rd| rd.flatten().any(|e| has_chart_extension(&e.path()) && e.path().is_file()))
+            .unwrap_or(false)
+    };
+    let name_of = |dir: &Path| dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
 
-    for i in 0..zip.len() {
-        let entry = zip.by_index(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
-        let rel = entry.enclosed_name().ok_or_else(|| format!("Unsafe path in ZIP entry #{i}"))?;
-        if let Some(first) = rel.components().next() {
-            let top = first.as_os_str().to_string_lossy().to_string();
-            // ignore macOS resource directory
-            if top != "__MACOSX" {
-                tops.insert(top);
-            }
+    if has_chart(src) {
+        return Ok(vec![(src.to_path_buf(), name_of(src))]);
+    }
+    let mut songs: Vec<(PathBuf, String)> = fs::read_dir(src)
+        .map_err(|e| format!("Could not read {}: {e}", src.display()))?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_dir() && has_chart(p))
+        .map(|p| { let name = name_of(&p); (p, name) })
+        .collect();
+    if songs.is_empty() {
+        return Err(format!("No song (.rlrr chart) found in {}", src.display()));
+    }
+    songs.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(songs)
+}
+
+/// Copies song folders, reporting files and bytes in `progress.extraction`
+fn copy_song_folders(copies: &[(PathBuf, PathBuf)], progress: &ProgressArc) -> Result<(), String> {
+    let mut files = Vec::new();
+    for (src, dst) in copies {
+        list_files(src, dst, &mut files).map_err(|e| format!("Could not read {}: {e}", src.display()))?;
+    }
+    progress.lock().extraction = Some(ExtractionProgress {
+        entries_total: files.len() as u64,
+        bytes_total: files.iter().map(|(_, _, size)| size).sum(),
+        ..Default::default()
+    });
+    for (from, to, size) in &files {
+        if let Some(x) = progress.lock().extraction.as_mut() {
+            x.current_file = Some(from.display().to_string());
+        }
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Could not create dir {}: {e}", parent.display()))?;
         }
+        fs::copy(from, to).map_err(|e| format!("Could not copy {}: {e}", from.display()))?;
+        if let Some(x) = progress.lock().extraction.as_mut() {
+            x.entries_done += 1;
+            x.bytes_written += size;
+        }
+    }
+    for (_, dst) in copies {
+        fs::create_dir_all(dst).map_err(|e| format!("Could not create dir {}: {e}", dst.display()))?;
     }
+    Ok(())
+}
 
-    if tops.len() == 1 {
-        Ok(tops.into_iter().next().unwrap())
-    } else if tops.is_empty() {
-        Err("ZIP appears empty".into())
-    } else {
-        Err(format!("ZIP has multiple top-level entries: {:?}", tops))
+/// Regular files under `src` with their destination under `dst` and their size
+fn list_files(src: &Path, dst: &Path, out: &mut Vec<(PathBuf, PathBuf, u64)>) -> io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        let to = dst.join(entry.file_name());
+        if ty.is_dir() {
+            list_files(&entry.path(), &to, out)?;
+        } else if ty.is_file() {
+            out.push((entry.path(), to, entry.metadata()?.len()));
+        }
+    }
+    Ok(())
+}
+
+/// Copies a local archive into the temp folder, reporting it as downloaded bytes
+async fn copy_local_archive(src: &Path, dst: &Path, progress: &ProgressArc) -> Result<(), String> {
+    let mut input = tokio::fs::File::open(src).await
+        .map_err(|e| format!("Could not open {}: {e}", src.display()))?;
+    let size = input.metadata().await.map(|m| m.len()).ok();
+    let mut out = tokio::fs::File::create(dst).await
+        .map_err(|e| format!("Could not create temporary file: {e}"))?;
+    {
+        let mut pg = progress.lock();
+        pg.total_bytes = size;
+        pg.bytes_downloaded = 0;
+    }
+    let mut buf = vec![0u8; 256 * 1024];
+    loop {
+        let n = input.read(&mut buf).await.map_err(|e| format!("Could not read {}: {e}", src.display()))?;
+        if n == 0 { break; }
+        out.write_all(&buf[..n]).await.map_err(|e| format!("Error writing file: {e}"))?;
+        progress.lock().bytes_downloaded += n as u64;
     }
+    out.flush().await.map_err(|e| format!("Could not flush to file: {e}"))
+}
+
+/// Moves the downloaded ZIP into a new song folder (copy fallback if rename fails)
+fn store_song_archive(zip_path: &Path, song_dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(song_dir).map_err(|e| format!("Could not create dir {}: {e}", song_dir.display()))?;
+    let dest = song_dir.join(SONG_ARCHIVE_FILE_NAME);
+    if fs::rename(zip_path, &dest).is_err() {
+        fs::copy(zip_path, &dest).map_err(|e| format!("Could not store archive {}: {e}", dest.display()))?;
+    }
+    Ok(())
 }
 
 /// Move directory with fallback to copy if rename fails (e.g., cross-device).
@@ -486,3 +1717,252 @@ fn copy_dir_recursive(src: &Path, dst: &Path) -> io::Result<()> {
 
 // Needed to use `resp.bytes_stream()`
 use futures_util::StreamExt;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_range(v: &str) -> Option<(u64, Option<u64>)> {
+        parse_content_range(Some(&header::HeaderValue::from_str(v).unwrap()))
+    }
+
+    #[test]
+    fn content_range_with_known_total() {
+        assert_eq!(content_range("bytes 100-199/200"), Some((100, Some(200))));
+        assert_eq!(content_range("bytes 0-0/1"), Some((0, Some(1))));
+    }
+
+    #[test]
+    fn content_range_with_unknown_total() {
+        assert_eq!(content_range("bytes 500-999/*"), Some((500, None)));
+    }
+
+    #[test]
+    fn content_range_without_a_range_is_rejected() {
+        // `bytes */N` only comes with a 416 and has no start offset to resume from
+        assert_eq!(content_range("bytes */1234"), None);
+        assert_eq!(content_range("items 0-9/10"), None);
+        assert_eq!(content_range("bytes 0-9"), None);
+        assert_eq!(parse_content_range(None), None);
+    }
+
+    fn retry_after(v: &str) -> Option<Duration> {
+        parse_retry_after(&header::HeaderValue::from_str(v).unwrap())
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(" 0 "), Some(Duration::ZERO));
+        assert_eq!(retry_after("soon"), None);
+    }
+
+    #[test]
+    fn retry_after_as_http_date() {
+        let at = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(90));
+        let delay = retry_after(&at).unwrap();
+        // the date has a one second resolution
+        assert!(delay > Duration::from_secs(87) && delay <= Duration::from_secs(90), "{delay:?}");
+        // a date in the past means retry now
+        assert_eq!(retry_after("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_cap() {
+        let policy = RetryPolicy { max_attempts: 5, initial_delay_ms: 1_000, max_delay_ms: 5_000 };
+        for _ in 0..100 {
+            for (retry, step) in [(0, 1_000), (1, 2_000), (2, 4_000), (3, 5_000), (40, 5_000)] {
+                let ms = policy.backoff(retry).as_millis() as u64;
+                assert!(ms >= step / 2 && ms <= step, "retry {retry}: {ms}ms");
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_is_waited_in_full_up_to_the_cap() {
+        let policy = RetryPolicy { max_attempts: 5, initial_delay_ms: 1_000, max_delay_ms: 30_000 };
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(20))), Some(Duration::from_secs(20)));
+        assert_eq!(policy.delay(3, Some(Duration::from_secs(30))), Some(Duration::from_secs(30)));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(60))), None);
+        assert!(policy.delay(0, None).unwrap() <= Duration::from_secs(1));
+    }
+
+    /// Serves `body` on a local port, once per entry of `cuts` (the bytes sent before dropping the
+    /// connection), honoring `Range: bytes=<n>-`. The server thread returns the Range headers it got.
+    fn serve(body: &'static [u8], cuts: Vec<Option<usize>>) -> (String, std::thread::JoinHandle<Vec<Option<String>>>) {
+        use std::io::{BufRead, BufReader, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/song.zip", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || cuts.into_iter().map(|cut| {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut range = None;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() { break; }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") { range = Some(value.trim().to_string()); }
+                }
+            }
+            let start = range.as_deref()
+                .and_then(|r| r.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok())
+                .unwrap_or(0);
+            let status = if start > 0 {
+                format!("206 Partial Content\r\nContent-Range: bytes {start}-{}/{}", body.len() - 1, body.len())
+            } else {
+                "200 OK".to_string()
+            };
+            write!(stream, "HTTP/1.1 {status}\r\nContent-Length: {}\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n", body.len() - start).unwrap();
+            let end = cut.unwrap_or(body.len());
+            let _ = stream.write_all(&body[start..end]);
+            range
+        }).collect());
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn failed_download_resumes_after_a_restart() {
+        const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+        let root = std::env::temp_dir().join(format!("parasync-resume-{}", std::process::id()));
+        let tmp_dir = root.join(".tmp").join("song");
+        fs::create_dir_all(&tmp_dir).unwrap();
+        let (part, resume_path) = (tmp_dir.join(PART_FILE_NAME), tmp_dir.join(RESUME_FILE_NAME));
+        let stale = root.join(".tmp").join("stale").join("extract");
+        fs::create_dir_all(&stale).unwrap();
+
+        let inner = ManagerInner::new();
+        let (_tx, mut control) = watch::channel(Control::Run);
+        let progress = ProgressArc::default();
+
+        // the first connection drops after 10 bytes: a resumable failure
+        let (url, server) = serve(BODY, vec![Some(10), None]);
+        let req = DownloadRequest {
+            key: "song".into(),
+            download_url: url,
+            dest_root: root.display().to_string(),
+            keep_archive: false,
+            expected_sha256: None,
+            expected_size: None,
+            local_path: None,
+            repository: None,
+            song: None,
+            update: false,
+            saf_dest: None,
+        };
+        let failed = inner.fetch_into_part(&req, &part, &resume_path, &mut StreamHash::default(), &progress, &mut control).await;
+        assert!(failed.is_err_and(|f| f.resumable));
+
+        // restart with only another download queued
+        remove_orphan_tmp_dirs(&root, &HashSet::from(["other"]));
+        assert!(!stale.exists());
+        assert_eq!(fs::read(&part).unwrap(), &BODY[..10]);
+
+        // starting it again continues from the part file
+        let resumed = inner.fetch_into_part(&req, &part, &resume_path, &mut StreamHash::default(), &progress, &mut control).await;
+        assert!(matches!(resumed, Ok(Fetch::Complete)));
+        assert_eq!(server.join().unwrap(), [None, Some("bytes=10-".to_string())]);
+        assert_eq!(fs::read(&part).unwrap(), BODY);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    fn slots(inner: &ManagerInner, max_concurrent: usize) {
+        inner.set_limits(DownloadLimits { max_concurrent, ..DownloadLimits::default() });
+    }
+
+    #[tokio::test]
+    async fn lowered_limit_takes_effect_as_busy_slots_finish() {
+        let inner = ManagerInner::new();
+        let (_tx, mut control) = watch::channel(Control::Run);
+        let a = inner.acquire_slot(&mut control).await.ok().unwrap();
+        let b = inner.acquire_slot(&mut control).await.ok().unwrap();
+        slots(&inner, 1);
+        drop(a);
+        assert_eq!(inner.limiter.available_permits(), 0);
+        drop(b);
+        assert_eq!(inner.limiter.available_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn raising_the_limit_again_keeps_busy_slots() {
+        let inner = ManagerInner::new();
+        let (_tx, mut control) = watch::channel(Control::Run);
+        let a = inner.acquire_slot(&mut control).await.ok().unwrap();
+        let b = inner.acquire_slot(&mut control).await.ok().unwrap();
+        slots(&inner, 1);
+        slots(&inner, 3);
+        assert_eq!(inner.limiter.available_permits(), 1);
+        drop((a, b));
+        assert_eq!(inner.limiter.available_permits(), 3);
+        assert_eq!(*inner.excess_slots.lock(), 0);
+    }
+
+    /// Writes a ZIP of `files` ("dir/" entries are folders, charts get a title) to the temp dir
+    fn write_zip(name: &str, files: &[&str]) -> PathBuf {
+        use std::io::Write;
+        let path = std::env::temp_dir().join(format!("parasync-plan-{}-{name}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(fs::File::create(&path).unwrap());
+        let options = zip::write::FileOptions::default();
+        for file in files {
+            if file.ends_with('/') {
+                zip.add_directory(*file, options).unwrap();
+                continue;
+            }
+            zip.start_file(*file, options).unwrap();
+            if file.ends_with(".rlrr") {
+                zip.write_all(br#"{"recordingMetadata":{"title":"My Song"}}"#).unwrap();
+            }
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    /// (folder name, source in the archive) of each planned song
+    fn plan(name: &str, files: &[&str]) -> Result<Vec<(String, Option<String>)>, String> {
+        let path = write_zip(name, files);
+        let planned = plan_song_folders(&path, ArchiveFormat::detect(&path)?, "key");
+        let _ = fs::remove_file(&path);
+        Ok(planned?.into_iter().map(|f| (f.name, f.source)).collect())
+    }
+
+    fn song(name: &str, source: Option<&str>) -> (String, Option<String>) {
+        (name.to_string(), source.map(str::to_string))
+    }
+
+    #[test]
+    fn plan_flat_and_single_folder_songs() {
+        assert_eq!(plan("flat", &["a.rlrr", "a.ogg"]).unwrap(), [song("My Song", None)]);
+        assert_eq!(plan("folder-readme", &["readme.txt", "Song/a.rlrr"]).unwrap(), [song("Song", Some("Song"))]);
+        assert_eq!(plan("folder", &["Song/a.rlrr", "Song/a.ogg"]).unwrap(), [song("Song", Some("Song"))]);
+        // a folder with a chart of its own is one song, even with charts in subfolders
+        assert_eq!(plan("own-chart", &["Pack/a.rlrr", "Pack/Extra/b.rlrr"]).unwrap(), [song("Pack", Some("Pack"))]);
+    }
+
+    #[test]
+    fn plan_descends_into_a_chartless_pack_folder() {
+        let songs = plan("pack", &["Pack/SongA/a.rlrr", "Pack/SongB/b.rlrr", "Pack/readme.txt"]).unwrap();
+        assert_eq!(songs, [song("SongA", Some("Pack/SongA")), song("SongB", Some("Pack/SongB"))]);
+
+        let songs = plan("pack-readme", &["readme.txt", "Pack/SongA/a.rlrr", "Pack/SongB/b.rlrr"]).unwrap();
+        assert_eq!(songs, [song("SongA", Some("Pack/SongA")), song("SongB", Some("Pack/SongB"))]);
+
+        let songs = plan("nested", &["Outer/Pack/SongA/a.rlrr", "Outer/Pack/SongB/b.rlrr"]).unwrap();
+        assert_eq!(songs, [song("SongA", Some("Outer/Pack/SongA")), song("SongB", Some("Outer/Pack/SongB"))]);
+    }
+
+    #[test]
+    fn plan_ignores_macos_files_and_folders_without_charts() {
+        let files = ["Pack/", "Pack/SongA/", "Pack/SongA/a.rlrr", "__MACOSX/x", "Pack/.DS_Store"];
+        assert_eq!(plan("macos", &files).unwrap(), [song("SongA", Some("Pack/SongA"))]);
+
+        let songs = plan("docs", &["SongA/a.rlrr", "SongB/b.rlrr", "Docs/x.txt"]).unwrap();
+        assert_eq!(songs, [song("SongA", Some("SongA")), song("SongB", Some("SongB"))]);
+    }
+
+    #[test]
+    fn plan_without_charts() {
+        // several folders without a chart: one song named after the key
+        assert_eq!(plan("no-charts", &["A/x.txt", "B/y.txt"]).unwrap(), [song("key", None)]);
+        assert!(plan("empty", &[]).is_err());
+    }
+}
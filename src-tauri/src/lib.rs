@@ -1,9 +1,20 @@
+mod audio_decode;
+mod audio_output;
 mod audio_service;
 mod audio_commands;
+mod archive_commands;
+mod archive_extract;
 mod downloads_service;
 mod downloads_commands;
+#[cfg(feature = "opus")]
+mod opus_decoder;
 mod saf_service;
 mod saf_commands;
+mod song_archive;
+mod song_manifest;
+mod song_validate;
+
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -16,10 +27,48 @@ pub fn run() {
             audio_commands::seek_audio,
             audio_commands::dispose_audio,
             audio_commands::audio_status,
+            audio_commands::audio_meters,
+            audio_commands::probe_audio,
             audio_commands::set_tracks_gain_by_path,
             audio_commands::mute_tracks_by_path,
+            audio_commands::set_tracks_pan_by_path,
+            audio_commands::set_tracks_offset_by_path,
+            audio_commands::set_tracks_automation_by_path,
+            audio_commands::clear_tracks_automation_by_path,
+            audio_commands::set_playback_rate,
+            audio_commands::set_loop_region,
+            audio_commands::get_mix_settings,
+            audio_commands::apply_mix_settings,
+            audio_commands::save_song_mix,
+            audio_commands::load_song_mix,
+            audio_commands::queue_song,
+            audio_commands::clear_song_queue,
+            audio_commands::song_queue,
+            audio_commands::set_crossfade,
+            audio_commands::set_output_buffer_size,
+            audio_commands::set_mix_block_size,
+            audio_commands::output_settings,
             downloads_commands::start_song_download,
             downloads_commands::downloads_status,
+            downloads_commands::start_song_update,
+            downloads_commands::check_song_updates,
+            downloads_commands::import_song_archive,
+            downloads_commands::import_song_folder,
+            downloads_commands::song_manifest,
+            downloads_commands::cancel_song_download,
+            downloads_commands::pause_song_download,
+            downloads_commands::resume_song_download,
+            downloads_commands::set_download_retry_policy,
+            downloads_commands::download_retry_policy,
+            downloads_commands::downloads_history,
+            downloads_commands::clear_downloads_history,
+            downloads_commands::set_downloads_history_retention,
+            downloads_commands::set_download_limits,
+            downloads_commands::download_limits,
+            downloads_commands::set_extract_limits,
+            downloads_commands::extract_limits,
+            archive_commands::list_song_archive,
+            archive_commands::read_song_archive_file,
             saf_commands::saf_select_dir,
             saf_commands::saf_get_dir,
             saf_commands::saf_copy_appdir_to_saf,
@@ -30,6 +79,14 @@ pub fn run() {
             saf_commands::saf_read_file,
             saf_commands::saf_remove,
         ])
+        .setup(|app| {
+            audio_service::AUDIO.set_app_handle(app.handle().clone());
+            match app.path().app_data_dir() {
+                Ok(dir) => downloads_service::DOWNLOADS.restore_queue(dir),
+                Err(e) => eprintln!("[downloads] no app data dir, the queue won't persist: {e}"),
+            }
+            Ok(())
+        })
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
@@ -0,0 +1,153 @@
+use audiopus::{coder::Decoder as LibOpus, packet::Packet as OpusPacket, Channels, MutSignals, SampleRate};
+use symphonia::core::{
+    audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
+    codecs::{CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS},
+    errors::{Error, Result},
+    formats::Packet,
+    support_codec,
+};
+
+/// Opus always decodes at 48 kHz
+const SAMPLE_RATE: u32 = 48_000;
+
+/// Longest Opus packet: 120 ms at 48 kHz
+const MAX_FRAMES: usize = 5_760;
+
+/// Opus decoder for symphonia, over libopus. symphonia 0.5 demuxes Opus (Ogg, Matroska/WebM)
+/// but has no decoder of its own. Mono and stereo streams only (channel mapping family 0 or 1).
+pub struct OpusDecoder {
+    opus: LibOpus,
+    params: CodecParameters,
+    channels: Channels,
+    pcm: Vec<f32>, // interleaved output of libopus
+    buf: AudioBuffer<f32>,
+    skip_frames: usize, // encoder delay (the stream's pre-skip) not dropped yet
+}
+
+// SAFETY: libopus keeps no thread-local state and its decoder is only used through `&mut self`
+unsafe impl Sync for OpusDecoder {}
+
+impl Decoder for OpusDecoder {
+    fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+        let layout = params.channels.ok_or(Error::Unsupported("opus: missing channel layout"))?;
+        let channels = match layout.count() {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(Error::Unsupported("opus: only mono and stereo streams are supported")),
+        };
+        let opus = LibOpus::new(SampleRate::Hz48000, channels)
+            .map_err(|_| Error::Unsupported("opus: could not create the decoder"))?;
+        Ok(Self {
+            opus,
+            params: params.clone(),
+            channels,
+            pcm: vec![0.0; MAX_FRAMES * layout.count()],
+            buf: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(SAMPLE_RATE, layout)),
+            skip_frames: params.delay.unwrap_or(0) as usize,
+        })
+    }
+
+    fn supported_codecs() -> &'static [CodecDescriptor] {
+        &[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus (libopus)")]
+    }
+
+    fn reset(&mut self) {
+        // a seek lands mid-stream: start from a clean state, the pre-skip only applies at the start
+        if let Ok(opus) = LibOpus::new(SampleRate::Hz48000, self.channels) {
+            self.opus = opus;
+        }
+        self.skip_frames = 0;
+    }
+
+    fn codec_params(&self) -> &CodecParameters {
+        &self.params
+    }
+
+    fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+        let input = OpusPacket::try_from(packet.buf()).map_err(|_| Error::DecodeError("opus: empty packet"))?;
+        let output = MutSignals::try_from(&mut self.pcm[..]).map_err(|_| Error::DecodeError("opus: no output buffer"))?;
+        let frames = self.opus.decode_float(Some(input), output, false)
+            .map_err(|_| Error::DecodeError("opus: invalid packet"))?;
+
+        let skip = self.skip_frames.min(frames);
+        self.skip_frames -= skip;
+        let count = self.buf.spec().channels.count();
+        self.buf.clear();
+        self.buf.render_reserved(Some(frames - skip));
+        for ch in 0..count {
+            for (i, sample) in self.buf.chan_mut(ch).iter_mut().enumerate() {
+                *sample = self.pcm[(skip + i) * count + ch];
+            }
+        }
+        Ok(self.buf.as_audio_buffer_ref())
+    }
+
+    fn finalize(&mut self) -> FinalizeResult {
+        FinalizeResult::default()
+    }
+
+    fn last_decoded(&self) -> AudioBufferRef<'_> {
+        self.buf.as_audio_buffer_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audiopus::{coder::Encoder, Application};
+    use symphonia::core::audio::Channels as Layout;
+
+    const FRAME: usize = 960; // 20 ms
+
+    fn params(delay: u32) -> CodecParameters {
+        let mut params = CodecParameters::new();
+        params
+            .for_codec(CODEC_TYPE_OPUS)
+            .with_sample_rate(SAMPLE_RATE)
+            .with_channels(Layout::FRONT_LEFT | Layout::FRONT_RIGHT)
+            .with_delay(delay);
+        params
+    }
+
+    fn encoded_tone(packets: usize) -> Vec<Vec<u8>> {
+        let encoder = Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio).unwrap();
+        (0..packets)
+            .map(|p| {
+                let pcm: Vec<f32> = (0..FRAME * 2)
+                    .map(|i| {
+                        let t = (p * FRAME + i / 2) as f32 / SAMPLE_RATE as f32;
+                        (t * 440.0 * std::f32::consts::TAU).sin() * 0.5
+                    })
+                    .collect();
+                let mut out = [0u8; 4000];
+                let len = encoder.encode_float(&pcm, &mut out).unwrap();
+                out[..len].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_stereo_and_drops_the_pre_skip() {
+        let mut decoder = OpusDecoder::try_new(&params(312), &DecoderOptions::default()).unwrap();
+        let mut frames = Vec::new();
+        let mut peak = 0f32;
+        for (ts, data) in encoded_tone(10).into_iter().enumerate() {
+            let packet = Packet::new_from_boxed_slice(0, ts as u64 * FRAME as u64, FRAME as u64, data.into());
+            let buf = decoder.decode(&packet).unwrap();
+            frames.push(buf.frames());
+            if let AudioBufferRef::F32(buf) = buf {
+                peak = buf.chan(1).iter().fold(peak, |m, s| m.max(s.abs()));
+            }
+        }
+        assert_eq!(frames[0], FRAME - 312);
+        assert!(frames[1..].iter().all(|&n| n == FRAME));
+        assert!(peak > 0.2, "tone came out silent (peak {peak})");
+    }
+
+    #[test]
+    fn rejects_surround_streams() {
+        let mut params = params(0);
+        params.with_channels(Layout::FRONT_LEFT | Layout::FRONT_RIGHT | Layout::FRONT_CENTRE);
+        assert!(OpusDecoder::try_new(&params, &DecoderOptions::default()).is_err());
+    }
+}
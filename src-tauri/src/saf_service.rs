@@ -2,6 +2,9 @@ use once_cell::sync::Lazy;
 
 pub static SAF: Lazy<SafService> = Lazy::new(SafService::new);
 
+/// Prefix of paths relative to the SAF songs root, e.g. `saf://Songs/MySong/drums.ogg`
+pub const SAF_PATH_PREFIX: &str = "saf://";
+
 pub struct SafService;
 
 impl SafService {
@@ -272,6 +275,40 @@ impl SafService {
         }
     }
 
+    /// Opens a file under the SAF root for reading, as a seekable `File` over a detached descriptor.
+    /// Tracks are decoded from it directly instead of being copied out of SAF first.
+    pub fn open_file(&self, path: String) -> Result<std::fs::File, String> {
+        #[cfg(target_os = "android")]
+        {
+            android_sys::with_env_activity(|env, activity| {
+                use jni::objects::JValue;
+                use std::os::fd::FromRawFd;
+                let j_path = env.new_string(&path).map_err(|e| e.to_string())?;
+                let fd = env
+                    .call_method(
+                        activity,
+                        "safOpenFd",
+                        "(Ljava/lang/String;)I",
+                        &[JValue::from(&j_path)],
+                    )
+                    .map_err(|e| format!("JNI call safOpenFd: {e:?}"))?
+                    .i()
+                    .map_err(|e| format!("{e:?}"))?;
+                if fd < 0 {
+                    Err(format!("File not found: {path}"))
+                } else {
+                    // the descriptor was detached on the Java side: the File owns and closes it
+                    Ok(unsafe { std::fs::File::from_raw_fd(fd) })
+                }
+            })
+        }
+        #[cfg(not(target_os = "android"))]
+        {
+            println!("Not implemented on this platform. {}", path);
+            Err("Not implemented on this platform".into())
+        }
+    }
+
     /// Remove a file or directory under the SAF root. If directory and recursive=true, delete its contents.
     pub fn remove(&self, path: String, recursive: bool) -> Result<bool, String> {
         #[cfg(target_os = "android")]
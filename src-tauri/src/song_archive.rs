@@ -0,0 +1,140 @@
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+use zip::CompressionMethod;
+
+use crate::downloads_service::DOWNLOADS;
+use crate::saf_service::{SAF, SAF_PATH_PREFIX};
+
+/// Separates the archive from the entry in paths like `<song_dir>/song.zip!/Song/drums.ogg`
+pub const ARCHIVE_ENTRY_SEPARATOR: &str = "!/";
+
+/// Name of the archive inside a library folder for songs kept compressed
+pub const SONG_ARCHIVE_FILE_NAME: &str = "song.zip";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// Splits `<archive>.zip!/<entry>` into its two parts. Other paths return `None`.
+/// The archive may be under the Android SAF songs root (`saf://Songs/x/song.zip!/drums.ogg`).
+pub fn split_archive_path(path: &str) -> Option<(&str, &str)> {
+    let idx = path.find(ARCHIVE_ENTRY_SEPARATOR)?;
+    let (archive, entry) = (&path[..idx], &path[idx + ARCHIVE_ENTRY_SEPARATOR.len()..]);
+    let is_zip = Path::new(archive).extension().is_some_and(|e| e.eq_ignore_ascii_case("zip"));
+    (is_zip && !entry.is_empty()).then_some((archive, entry))
+}
+
+/// Archive paths below are local files or `saf://<path>` files under the SAF songs root
+pub fn list_entries(archive: &str) -> Result<Vec<ArchiveEntry>, String> {
+    let mut zip = open_zip(archive)?;
+    let mut entries = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let entry = zip.by_index_raw(i).map_err(|e| format!("Invalid ZIP entry: {e}"))?;
+        entries.push(ArchiveEntry { name: entry.name().to_string(), size: entry.size(), is_dir: entry.is_dir() });
+    }
+    Ok(entries)
+}
+
+/// Reads a whole entry (e.g. an .rlrr chart or the cover image)
+pub fn read_entry(archive: &str, name: &str) -> Result<Vec<u8>, String> {
+    let mut reader = open_entry(archive, name)?;
+    let mut data = Vec::with_capacity(reader.size() as usize);
+    reader.read_to_end(&mut data).map_err(|e| format!("Could not read {name}: {e}"))?;
+    Ok(data)
+}
+
+/// Opens one entry for random access. Stored entries are read in place from the archive;
+/// compressed ones are inflated into memory once, since deflate streams can't seek. Entries over
+/// the extraction limit (`max_entry_bytes`) are refused, whatever their header declares.
+pub fn open_entry(archive: &str, name: &str) -> Result<ZipEntryReader, String> {
+    let mut zip = open_zip(archive)?;
+    let mut entry = zip.by_name(name)
+        .map_err(|e| format!("Entry {name} not found in {archive}: {e}"))?;
+    if entry.is_dir() { return Err(format!("{name} is a directory")); }
+
+    let (data, len) = if entry.compression() == CompressionMethod::Stored {
+        let (start, len) = (entry.data_start(), entry.size());
+        drop(entry);
+        (EntryData::Stored { file: zip.into_inner(), start }, len)
+    } else {
+        let max = DOWNLOADS.extract_limits().max_entry_bytes;
+        let too_large = || format!("{name} is larger than the {max} bytes allowed per entry");
+        if entry.size() > max { return Err(too_large()); }
+        let mut buf = Vec::with_capacity(entry.size() as usize);
+        // the header can lie: stop inflating one byte past the limit
+        (&mut entry).take(max.saturating_add(1)).read_to_end(&mut buf)
+            .map_err(|e| format!("Could not inflate {name}: {e}"))?;
+        let len = buf.len() as u64;
+        if len > max { return Err(too_large()); }
+        (EntryData::Inflated(buf), len)
+    };
+    Ok(ZipEntryReader { data, len, pos: 0 })
+}
+
+fn open_zip(archive: &str) -> Result<zip::ZipArchive<File>, String> {
+    // SAF hands out a seekable descriptor, read in place like a local file
+    let file = match archive.strip_prefix(SAF_PATH_PREFIX) {
+        Some(rel) => SAF.open_file(rel.to_string())?,
+        None => File::open(archive).map_err(|e| format!("Could not open ZIP {archive}: {e}"))?,
+    };
+    zip::ZipArchive::new(file).map_err(|e| format!("Invalid ZIP {archive}: {e}"))
+}
+
+enum EntryData {
+    Stored { file: File, start: u64 }, // entry bytes live at `start..start + len` in the archive
+    Inflated(Vec<u8>),
+}
+
+/// `Read + Seek` over a single ZIP entry
+pub struct ZipEntryReader {
+    data: EntryData,
+    len: u64,
+    pos: u64,
+}
+
+impl ZipEntryReader {
+    pub fn size(&self) -> u64 { self.len }
+}
+
+impl Read for ZipEntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.len.saturating_sub(self.pos);
+        let n = (buf.len() as u64).min(left) as usize;
+        if n == 0 { return Ok(0); }
+        let read = match &mut self.data {
+            EntryData::Stored { file, start } => {
+                file.seek(SeekFrom::Start(*start + self.pos))?;
+                file.read(&mut buf[..n])?
+            }
+            EntryData::Inflated(bytes) => {
+                let from = self.pos as usize;
+                buf[..n].copy_from_slice(&bytes[from..from + n]);
+                n
+            }
+        };
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for ZipEntryReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(p) => p as i128,
+            SeekFrom::End(d) => self.len as i128 + d as i128,
+            SeekFrom::Current(d) => self.pos as i128 + d as i128,
+        };
+        if target < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the entry"));
+        }
+        self.pos = target as u64;
+        Ok(self.pos)
+    }
+}
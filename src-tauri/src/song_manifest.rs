@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{fs, io, path::Path};
+
+/// Per-song manifest written next to the song files
+pub const MANIFEST_FILE_NAME: &str = "parasync.json";
+
+/// Current manifest schema. Manifests without a `version` are version 1 (`{ "id" }` only).
+pub const MANIFEST_VERSION: u32 = 2;
+
+/// Keys owned by `SongManifest`; unset ones are removed from the file on write
+const MANIFEST_FIELDS: &[&str] = &[
+    "version", "id", "pack", "source", "installed_at_ms", "archive_sha256", "archive_size", "archive", "song",
+];
+
+/// Typed view of `parasync.json`. Keys owned by other features (the saved "mix"...) are kept in `other`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SongManifest {
+    pub version: u32,
+    /// Song id in its repository (or `local-<path hash>` for imports); `<pack>/<folder>` in a pack
+    pub id: String,
+    /// Id of the pack the song was installed with, if it came in one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SongSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at_ms: Option<u64>,
+    /// SHA-256 (hex) and size of the archive the song was installed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_size: Option<u64>,
+    /// Archive stored in the song folder (kept compressed), relative to the folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive: Option<String>,
+    /// Repository metadata of the song at install time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub song: Option<SongMetadata>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Where an installed song came from
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SongSource {
+    /// Repository name, from its configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download_url: Option<String>,
+    /// Archive or folder the song was imported from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>,
+}
+
+/// Snapshot of the repository's `Song` entry
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SongMetadata {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub difficulties: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_at: Option<String>,
+}
+
+/// Reads `<dir>/parasync.json` as a JSON object. A missing manifest returns `None`; one that isn't
+/// a JSON object is an `InvalidData` error, so it never gets overwritten by a read-modify-write.
+pub fn read_manifest(dir: &Path) -> io::Result<Option<Map<String, Value>>> {
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    let contents = match fs::read_to_string(&manifest_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            if e.kind() == io::ErrorKind::NotFound { return Ok(None); }
+            return Err(e);
+        }
+    };
+
+    match serde_json::from_str::<Value>(&contents) {
+        Ok(Value::Object(map)) => Ok(Some(map)),
+        Ok(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "not a JSON object")),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+pub fn write_manifest(dir: &Path, manifest: &Map<String, Value>) -> Result<(), String> {
+    let data = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
+    let manifest_path = dir.join(MANIFEST_FILE_NAME);
+    fs::write(&manifest_path, data)
+        .map_err(|e| format!("Failed to write manifest {}: {e}", manifest_path.display()))
+}
+
+/// Read-modify-write of the manifest, keeping every key `f` does not touch.
+/// Fails without writing if the existing manifest can't be read.
+pub fn update_manifest<F>(dir: &Path, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut Map<String, Value>),
+{
+    let mut manifest = read_manifest(dir)
+        .map_err(|e| format!("Failed to read manifest in {}: {e}", dir.display()))?
+        .unwrap_or_default();
+    f(&mut manifest);
+    write_manifest(dir, &manifest)
+}
+
+/// Reads `<dir>/parasync.json` and migrates it to the current schema (in memory only).
+/// Manifests from a newer version of the app are rejected rather than misread.
+pub fn read_song_manifest(dir: &Path) -> Result<Option<SongManifest>, String> {
+    let Some(mut manifest) = read_manifest(dir)
+        .map_err(|e| format!("Failed to read manifest in {}: {e}", dir.display()))?
+    else {
+        return Ok(None);
+    };
+    migrate_manifest(&mut manifest)?;
+    serde_json::from_value(Value::Object(manifest))
+        .map(Some)
+        .map_err(|e| format!("Invalid manifest in {}: {e}", dir.display()))
+}
+
+/// Writes the typed manifest. Keys of the existing file that `SongManifest` doesn't model are kept.
+pub fn write_song_manifest(dir: &Path, manifest: &SongManifest) -> Result<(), String> {
+    let Value::Object(fields) = serde_json::to_value(manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {e}"))?
+    else {
+        return Err("Manifest is not a JSON object".into());
+    };
+    update_manifest(dir, |m| {
+        m.retain(|k, _| !MANIFEST_FIELDS.contains(&k.as_str()));
+        m.extend(fields);
+    })
+}
+
+/// Copies the keys of `from`'s manifest that `SongManifest` doesn't own (user data such as the
+/// saved mix) into `to`'s manifest. Nothing to do if `from` has no manifest.
+pub fn copy_user_fields(from: &Path, to: &Path) -> Result<(), String> {
+    let Some(old) = read_manifest(from)
+        .map_err(|e| format!("Failed to read manifest in {}: {e}", from.display()))?
+    else {
+        return Ok(());
+    };
+    update_manifest(to, |m| {
+        m.extend(old.into_iter().filter(|(k, _)| !MANIFEST_FIELDS.contains(&k.as_str())));
+    })
+}
+
+/// Upgrades a raw manifest one version at a time up to `MANIFEST_VERSION`
+fn migrate_manifest(manifest: &mut Map<String, Value>) -> Result<(), String> {
+    let mut version = manifest.get("version").and_then(Value::as_u64).unwrap_or(1);
+    if version > MANIFEST_VERSION as u64 {
+        return Err(format!("Manifest version {version} is newer than supported ({MANIFEST_VERSION})"));
+    }
+    if version == 1 {
+        // v1 only had "id" (plus "archive" / "mix"); the provenance fields are unknown
+        if !manifest.get("id").is_some_and(Value::is_string) {
+            manifest.insert("id".into(), Value::String(String::new()));
+        }
+        version = 2;
+    }
+    manifest.insert("version".into(), Value::from(version));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn migrated(manifest: Value) -> Result<Map<String, Value>, String> {
+        let Value::Object(mut map) = manifest else { unreachable!() };
+        migrate_manifest(&mut map)?;
+        Ok(map)
+    }
+
+    #[test]
+    fn v1_gets_a_version_and_keeps_its_keys() {
+        let m = migrated(json!({ "id": "abc", "archive": "song.zip", "mix": { "gains": {} } })).unwrap();
+        assert_eq!(Value::Object(m.clone()), json!({
+            "version": 2, "id": "abc", "archive": "song.zip", "mix": { "gains": {} },
+        }));
+        let typed: SongManifest = serde_json::from_value(Value::Object(m)).unwrap();
+        assert_eq!(typed.id, "abc");
+        assert_eq!(typed.archive.as_deref(), Some("song.zip"));
+        assert!(typed.other.contains_key("mix"));
+    }
+
+    #[test]
+    fn v1_without_a_usable_id() {
+        assert_eq!(migrated(json!({})).unwrap()["id"], "");
+        assert_eq!(migrated(json!({ "id": 42 })).unwrap()["id"], "");
+        assert_eq!(migrated(json!({ "version": 1 })).unwrap()["version"], 2);
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let v2 = json!({ "version": 2, "id": "pack/song", "pack": "pack", "installed_at_ms": 5 });
+        assert_eq!(Value::Object(migrated(v2.clone()).unwrap()), v2);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let err = migrated(json!({ "version": MANIFEST_VERSION + 1, "id": "abc" })).unwrap_err();
+        assert!(err.contains("newer than supported"), "{err}");
+    }
+}
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::audio_decode;
+use crate::song_archive::{self, ARCHIVE_ENTRY_SEPARATOR, SONG_ARCHIVE_FILE_NAME};
+
+/// Problems found in an installed song. Errors make it unplayable; warnings don't.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SongValidation {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// The parts of a `.rlrr` chart the player needs
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Chart {
+    recording_metadata: ChartMetadata,
+    audio_file_data: ChartAudio,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartMetadata {
+    #[serde(default)]
+    cover_image_path: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChartAudio {
+    #[serde(default)]
+    song_tracks: Vec<String>,
+    #[serde(default)]
+    drum_tracks: Vec<String>,
+}
+
+/// Files of a song, loose in its folder or kept compressed in `<folder>/song.zip`
+enum SongFiles {
+    Dir(PathBuf),
+    /// The archive and the folder of the charts inside it ("" at the root)
+    Archive(PathBuf, String),
+}
+
+impl SongFiles {
+    /// Chart file names, relative to the chart folder
+    fn open(dir: &Path) -> Result<(Self, Vec<String>), String> {
+        let archive = dir.join(SONG_ARCHIVE_FILE_NAME);
+        if !archive.is_file() {
+            let charts = fs::read_dir(dir)
+                .map_err(|e| format!("Could not read {}: {e}", dir.display()))?
+                .flatten()
+                .filter(|e| e.path().is_file())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|name| is_chart(name))
+                .collect();
+            return Ok((SongFiles::Dir(dir.to_path_buf()), charts));
+        }
+        // the charts of a kept archive sit at its root or in its single top-level folder
+        let names: Vec<String> = song_archive::list_entries(&archive.to_string_lossy())?
+            .into_iter()
+            .filter(|e| !e.is_dir && is_chart(&e.name))
+            .map(|e| e.name)
+            .collect();
+        let depth = |name: &str| name.matches('/').count();
+        let Some(top) = names.iter().min_by_key(|n| depth(n)) else {
+            return Ok((SongFiles::Archive(archive, String::new()), Vec::new()));
+        };
+        let prefix = top.rsplit_once('/').map(|(p, _)| format!("{p}/")).unwrap_or_default();
+        let charts = names.iter()
+            .filter_map(|n| n.strip_prefix(&prefix))
+            .filter(|n| !n.contains('/'))
+            .map(str::to_string)
+            .collect();
+        Ok((SongFiles::Archive(archive, prefix), charts))
+    }
+
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        match self {
+            SongFiles::Dir(dir) => fs::read(dir.join(name)).map_err(|e| format!("Could not read {name}: {e}")),
+            SongFiles::Archive(archive, prefix) => song_archive::read_entry(&archive.to_string_lossy(), &format!("{prefix}{name}")),
+        }
+    }
+
+    /// Path the audio decoder opens (see `audio_decode::open_media`)
+    fn media_path(&self, name: &str) -> String {
+        match self {
+            SongFiles::Dir(dir) => dir.join(name).display().to_string(),
+            SongFiles::Archive(archive, prefix) => {
+                format!("{}{ARCHIVE_ENTRY_SEPARATOR}{prefix}{name}", archive.display())
+            }
+        }
+    }
+}
+
+/// Checks that a song folder is playable: one chart (one `.rlrr` per difficulty, sharing a
+/// base name), every chart parses, every track it references exists and decodes, and the
+/// cover is a readable image. A missing or unreadable cover is only a warning.
+pub fn validate_song_dir(dir: &Path) -> SongValidation {
+    let mut report = SongValidation::default();
+    let (files, chart_names) = match SongFiles::open(dir) {
+        Ok(found) => found,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+    if chart_names.is_empty() {
+        report.errors.push("No .rlrr chart found".into());
+        return report;
+    }
+    let bases: BTreeSet<&str> = chart_names.iter().map(|n| chart_base_name(n)).collect();
+    if bases.len() > 1 {
+        let list: Vec<&str> = bases.into_iter().collect();
+        report.errors.push(format!("Several songs in one folder: {}", list.join(", ")));
+        return report;
+    }
+
+    let mut tracks = BTreeSet::new();
+    let mut covers = BTreeSet::new();
+    for name in &chart_names {
+        let chart = files.read(name).and_then(|data| parse_chart(&data).map_err(|e| format!("{name}: {e}")));
+        match chart {
+            Ok(chart) => {
+                let audio = chart.audio_file_data;
+                if audio.song_tracks.is_empty() && audio.drum_tracks.is_empty() {
+                    report.errors.push(format!("{name} references no audio track"));
+                }
+                tracks.extend(audio.song_tracks.into_iter().chain(audio.drum_tracks));
+                covers.insert(chart.recording_metadata.cover_image_path);
+            }
+            Err(e) => report.errors.push(e),
+        }
+    }
+
+    for track in tracks.iter().filter(|t| !t.trim().is_empty()) {
+        if let Some(e) = audio_decode::probe_file(files.media_path(track)).error {
+            report.errors.push(format!("Track {track}: {e}"));
+        }
+    }
+
+    for cover in covers {
+        if cover.trim().is_empty() {
+            report.warnings.push("No cover image".into());
+            continue;
+        }
+        match files.read(&cover) {
+            Ok(data) if is_image(&data) => {}
+            Ok(_) => report.warnings.push(format!("Cover {cover} is not a PNG, JPEG, GIF, WebP or BMP image")),
+            Err(e) => report.warnings.push(format!("Cover unreadable: {e}")),
+        }
+    }
+    report
+}
+
+/// Parses a chart like the frontend does: UTF-8 or UTF-16 (with or without BOM)
+fn parse_chart(data: &[u8]) -> Result<Chart, String> {
+    serde_json::from_str(decode_chart_text(data).trim_start_matches('\u{feff}'))
+        .map_err(|e| format!("invalid chart: {e}"))
+}
+
+fn decode_chart_text(data: &[u8]) -> String {
+    let utf16 = |big_endian: bool, bytes: &[u8]| {
+        let units: Vec<u16> = bytes.chunks_exact(2)
+            .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) })
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match data {
+        [0xff, 0xfe, rest @ ..] => utf16(false, rest),
+        [0xfe, 0xff, rest @ ..] => utf16(true, rest),
+        // UTF-16LE without BOM: every second byte is 0 for ASCII
+        [a, 0, b, 0, ..] if *a != 0 && *b != 0 => utf16(false, data),
+        _ => String::from_utf8_lossy(data).into_owned(),
+    }
+}
+
+fn is_chart(name: &str) -> bool {
+    name.to_ascii_lowercase().ends_with(".rlrr")
+}
+
+/// "Song_Expert.rlrr" -> "Song", as the library groups difficulties
+fn chart_base_name(name: &str) -> &str {
+    let file = name.rsplit('/').next().unwrap_or(name);
+    let stem = &file[..file.len() - ".rlrr".len()];
+    stem.rsplit_once('_').map(|(base, _)| base).unwrap_or(stem)
+}
+
+fn is_image(data: &[u8]) -> bool {
+    data.starts_with(b"\x89PNG\r\n\x1a\n")
+        || data.starts_with(&[0xff, 0xd8, 0xff])
+        || data.starts_with(b"GIF8")
+        || (data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP")
+        || data.starts_with(b"BM")
+}
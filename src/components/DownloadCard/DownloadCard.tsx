@@ -17,10 +17,25 @@ interface DownloadCardProps {
 const DownloadCard = ({ downloadInfo }: DownloadCardProps) => {
   const { status, song } = downloadInfo;
   const progressPercentage = Math.round(status.progress * 100);
+  const extraction = status.extraction;
+  const extractionPercentage = extraction?.bytes_total
+    ? Math.round((extraction.bytes_written / extraction.bytes_total) * 100)
+    : null;
 
   const getStatusText = () => {
-    if (status.extracting) return "Extracting...";
+    if (status.validating) return "Checking...";
+    if (status.extracting)
+      return extractionPercentage !== null
+        ? `Extracting... ${extractionPercentage}%`
+        : "Extracting...";
     if (status.progress >= 1) return "Completed";
+    if (status.retry_at_ms) {
+      const secs = Math.max(
+        0,
+        Math.ceil((status.retry_at_ms - Date.now()) / 1000),
+      );
+      return `Retrying in ${secs}s (attempt ${status.attempt + 1})`;
+    }
     return `Downloading... ${progressPercentage}%`;
   };
 
@@ -31,6 +46,12 @@ const DownloadCard = ({ downloadInfo }: DownloadCardProps) => {
   };
 
   const formatProgress = () => {
+    if (status.extracting && extraction) {
+      const files = `${extraction.entries_done} / ${extraction.entries_total} files`;
+      return extraction.current_file
+        ? `${files} · ${extraction.current_file}`
+        : files;
+    }
     if (!status.total_bytes) return `${formatBytes(status.bytes_downloaded)}`;
     return `${formatBytes(status.bytes_downloaded)} / ${formatBytes(status.total_bytes)}`;
   };
@@ -65,7 +86,7 @@ const DownloadCard = ({ downloadInfo }: DownloadCardProps) => {
                 ) : undefined
               }
             />
-            <Typography variant="caption" color="text.secondary">
+            <Typography variant="caption" color="text.secondary" noWrap>
               {formatProgress()}
             </Typography>
           </Box>
@@ -79,7 +100,13 @@ const DownloadCard = ({ downloadInfo }: DownloadCardProps) => {
             />
           )}
           {status.extracting && (
-            <LinearProgress sx={{ height: 8, borderRadius: 1 }} />
+            <LinearProgress
+              variant={
+                extractionPercentage !== null ? "determinate" : "indeterminate"
+              }
+              value={extractionPercentage ?? undefined}
+              sx={{ height: 8, borderRadius: 1 }}
+            />
           )}
         </Box>
       </Box>
@@ -1,6 +1,4 @@
-import { readFile } from "@tauri-apps/plugin-fs";
-import { IS_ANDROID } from "../../utils/mobile";
-import { SafManager } from "../../utils/saf";
+import { readSongFile } from "../../utils/archive";
 
 /**
  * Releases the resources used by a URL object
@@ -12,14 +10,12 @@ export const releaseFileUrl = (url?: string): void => {
 };
 
 /**
- * Loads a file from the song folder
+ * Loads a file from the song folder (or from inside its kept archive)
  */
 export const loadFile = async (filename: string): Promise<string | null> => {
   try {
     // Try to get the file directly from the song folder
-    const file = IS_ANDROID
-      ? await SafManager.getInstance().readFile(filename)
-      : await readFile(filename);
+    const file = await readSongFile(filename);
 
     const blob = new Blob([file], { type: "application/octet-stream" });
     return URL.createObjectURL(blob);
@@ -15,7 +15,11 @@ import SongCard from "../../components/SongCard/SongCard";
 import { useLocalSongs } from "../../context/SongsContext";
 import useSongsPath from "../../hooks/useSongsPath";
 import { Difficulty, LocalSong } from "../../types/songs";
-import { deleteSong, selectSongsDirectory } from "../../utils/fs";
+import {
+  deleteSong,
+  selectSongsDirectory,
+  songFolderOf,
+} from "../../utils/fs";
 import { IS_ANDROID } from "../../utils/mobile";
 import { SafManager } from "../../utils/saf";
 import { CARD_SIZE } from "../../utils/songs";
@@ -60,10 +64,7 @@ const MySongsPage = () => {
 
   const handleDeleteSelected = async () => {
     const deletePromises = selectedSongs.map(async (baseFileName) => {
-      const songFolder =
-        songsPath +
-        "/" +
-        baseFileName.substring(0, baseFileName.lastIndexOf("/"));
+      const songFolder = songsPath + "/" + songFolderOf(baseFileName);
       return deleteSong(songFolder);
     });
 
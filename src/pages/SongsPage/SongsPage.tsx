@@ -60,7 +60,9 @@ const SongsPage = () => {
       const data: { song: Song; localSong?: LocalSong } = {
         song,
         localSong: localSongs?.find(
-          (localSong) => localSong.song?.id && localSong.song.id === song.id,
+          (localSong) =>
+            (localSong.song?.id && localSong.song.id === song.id) ||
+            localSong.packId === song.id,
         ),
       };
       return data;
@@ -154,10 +156,24 @@ const SongsPage = () => {
   const handleDownload = async (song: Song) => {
     if (!song.downloadUrl || !songsPath || !repoRef.current) return;
     setSongsDownloadStates((prev) => ({ ...prev, [song.id]: "downloading" }));
+    let state: DownloadState = "downloaded";
     try {
-      await downloadManagerRef.current.startAndWait(song.id, song, songsPath);
+      const warnings = await downloadManagerRef.current.startAndWait(
+        song.id,
+        song,
+        songsPath,
+        repoRef.current.config.name,
+      );
+      if (warnings.length > 0) {
+        alert(
+          `"${song.title}" was installed with warnings:\n${warnings.join("\n")}`,
+        );
+      }
+    } catch (error) {
+      state = "not-downloaded";
+      alert(`The download of "${song.title}" failed: ${error}`);
     } finally {
-      setSongsDownloadStates((prev) => ({ ...prev, [song.id]: "downloaded" }));
+      setSongsDownloadStates((prev) => ({ ...prev, [song.id]: state }));
       refreshLocalSongs(localSongs);
     }
   };
@@ -62,9 +62,35 @@ export type Song = {
   coverUrl?: string;
   difficulties: Difficulty[];
   downloadUrl?: string;
+  downloadSha256?: string; // expected SHA-256 (hex) of the archive, if the repository publishes it
+  downloadSize?: number; // expected archive size in bytes
+};
+
+// Contents of a song's parasync.json (schema version 2)
+export type SongManifest = {
+  version: number;
+  id: string; // "<pack>/<folder>" for a song installed with a pack
+  pack?: string; // repository id of that pack
+  source?: {
+    repository?: string;
+    download_url?: string;
+    local_path?: string;
+  };
+  installed_at_ms?: number;
+  archive_sha256?: string;
+  archive_size?: number;
+  archive?: string;
+  song?: {
+    title: string;
+    artist: string;
+    difficulties: Difficulty[];
+    uploadedBy?: string;
+    uploadedAt?: string;
+  };
 };
 
 export type LocalSong = {
   song?: Song;
   baseFileName: string;
+  packId?: string; // repository id of the pack the song was installed with
 };
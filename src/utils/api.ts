@@ -99,10 +99,12 @@ export class SongRepository {
       "difficulties",
       "downloadUrl",
     ];
+    // optional: repositories that publish checksums get them verified
+    const optionalFields = ["downloadSha256", "downloadSize"];
 
     // check for invalid fields
     for (const field of Object.keys(this.config.response.fields)) {
-      if (!validFields.includes(field)) {
+      if (!validFields.includes(field) && !optionalFields.includes(field)) {
         throw new Error(`Invalid field "${field}" in response.fields`);
       }
     }
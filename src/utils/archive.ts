@@ -0,0 +1,68 @@
+import { invoke } from "@tauri-apps/api/core";
+import { readFile } from "@tauri-apps/plugin-fs";
+import { IS_ANDROID } from "./mobile";
+import { SafManager } from "./saf";
+
+// Archive kept in the folder of a song installed compressed
+export const SONG_ARCHIVE = "song.zip";
+
+// Separates the archive from the entry in paths like `<song dir>/song.zip!/Song/drums.ogg`,
+// the same paths the audio backend decodes from
+export const ARCHIVE_ENTRY_SEPARATOR = "!/";
+
+// Prefix of paths under the Android SAF songs root, as the backend opens them
+const SAF_PATH_PREFIX = "saf://";
+
+export type ArchiveEntry = {
+  name: string; // path inside the archive, "/"-separated
+  size: number;
+  is_dir: boolean;
+};
+
+// On Android song paths are relative to the SAF songs root; the backend opens them from there
+const backendArchivePath = (archivePath: string): string =>
+  IS_ANDROID && !archivePath.startsWith(SAF_PATH_PREFIX)
+    ? SAF_PATH_PREFIX + archivePath
+    : archivePath;
+
+export const listSongArchive = async (
+  archivePath: string,
+): Promise<ArchiveEntry[]> => {
+  return await invoke("list_song_archive", {
+    archivePath: backendArchivePath(archivePath),
+  });
+};
+
+export const readSongArchiveFile = async (
+  archivePath: string,
+  entry: string,
+): Promise<Uint8Array> => {
+  const data: number[] = await invoke("read_song_archive_file", {
+    archivePath: backendArchivePath(archivePath),
+    entry,
+  });
+  return new Uint8Array(data);
+};
+
+// Splits `<archive>.zip!/<entry>`; null for plain paths
+export const splitArchivePath = (
+  filePath: string,
+): { archivePath: string; entry: string } | null => {
+  const idx = filePath.indexOf(ARCHIVE_ENTRY_SEPARATOR);
+  if (idx < 0) return null;
+  const archivePath = filePath.substring(0, idx);
+  const entry = filePath.substring(idx + ARCHIVE_ENTRY_SEPARATOR.length);
+  if (!archivePath.toLowerCase().endsWith(".zip") || !entry) return null;
+  return { archivePath, entry };
+};
+
+// Reads a song file, from the song folder or from inside its kept archive
+export const readSongFile = async (filePath: string): Promise<Uint8Array> => {
+  const inArchive = splitArchivePath(filePath);
+  if (inArchive) {
+    return await readSongArchiveFile(inArchive.archivePath, inArchive.entry);
+  }
+  return IS_ANDROID
+    ? await SafManager.getInstance().readFile(filePath)
+    : await readFile(filePath);
+};
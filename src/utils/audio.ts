@@ -1,12 +1,16 @@
 import { invoke } from "@tauri-apps/api/core";
 import { Loadable } from "excalibur";
-import { clearPlayerCache, copyTracksToAndroidCache } from "./fs";
 import { IS_ANDROID } from "./mobile";
-import { v4 as uuid } from "uuid";
+
+// Tracks on Android are decoded in place from the SAF songs folder
+const SAF_PATH_PREFIX = "saf://";
 
 export type AudioStatus = {
   position_secs: number;
   is_playing: boolean;
+  output_latency_ms: number | null;
+  buffer_frames: number | null;
+  block_frames: number;
 };
 
 export class SongAudioManager implements Loadable<{}> {
@@ -17,7 +21,6 @@ export class SongAudioManager implements Loadable<{}> {
   private songTrackPaths: string[];
   private drumsTrackPaths: string[];
   private _drumsMuted = false;
-  private _cacheKey = uuid();
   private _isDisposed = false;
 
   private _interval: number = 0;
@@ -66,23 +69,14 @@ export class SongAudioManager implements Loadable<{}> {
     }
 
     this._isLoaded = false;
-    let allPaths = [...this.songTrackPaths, ...this.drumsTrackPaths];
 
     if (IS_ANDROID) {
-      try {
-        this.songTrackPaths = await copyTracksToAndroidCache(
-          this.songTrackPaths,
-          this._cacheKey,
-        );
-        this.drumsTrackPaths = await copyTracksToAndroidCache(
-          this.drumsTrackPaths,
-          this._cacheKey,
-        );
-        allPaths = [...this.songTrackPaths, ...this.drumsTrackPaths];
-      } catch (error) {
-        console.log("Error copying tracks to Android cache: " + error);
-      }
+      const toSafPath = (track: string) =>
+        track.startsWith(SAF_PATH_PREFIX) ? track : SAF_PATH_PREFIX + track;
+      this.songTrackPaths = this.songTrackPaths.map(toSafPath);
+      this.drumsTrackPaths = this.drumsTrackPaths.map(toSafPath);
     }
+    const allPaths = [...this.songTrackPaths, ...this.drumsTrackPaths];
     await invoke("load_audio", {
       paths: allPaths,
     } as any);
@@ -123,7 +117,6 @@ export class SongAudioManager implements Loadable<{}> {
     this._isDisposed = true;
     clearInterval(this._interval);
     await invoke("dispose_audio");
-    await clearPlayerCache(this._cacheKey);
   }
 
   async toggleDrums(mute: boolean) {
@@ -1,9 +1,7 @@
 import { invoke } from "@tauri-apps/api/core";
-import { Song } from "../types/songs";
+import { Song, SongManifest } from "../types/songs";
 import { IS_ANDROID } from "./mobile";
-import { SafManager } from "./saf";
-import { getAndroidTmpFolder, removeAndroidTmpFolder } from "./fs";
-import { v4 as uuid } from "uuid";
+import { getAndroidTmpFolder } from "./fs";
 
 // Utility function to format bytes
 export const formatBytes = (bytes: number, decimals = 2): string => {
@@ -18,17 +16,81 @@ export const formatBytes = (bytes: number, decimals = 2): string => {
   return parseFloat((bytes / Math.pow(k, i)).toFixed(dm)) + " " + sizes[i];
 };
 
+type ExtractionProgress = {
+  entries_done: number;
+  entries_total: number;
+  bytes_written: number;
+  bytes_total: number; // uncompressed size of the archive
+  current_file?: string | null;
+};
+
 // Type definition matching what the backend returns
 type DownloadStatus = {
   key: string; // == song id
   bytes_downloaded: number;
   total_bytes?: number | null; // can be undefined if server doesn't send Content-Length
   progress: number; // 0..1 (download). Goes to 1.0 when download finishes
-  extracting: boolean; // true while extracting (no percentage)
+  extracting: boolean; // true while extracting
+  extraction?: ExtractionProgress | null; // details while extracting
+  paused: boolean;
+  attempt: number; // current request attempt (retries after transient failures)
+  validating: boolean; // true while checking the installed song is playable
+  warnings: string[]; // non-fatal problems found by the validation
+  retry_at_ms?: number | null; // when the next attempt starts (ms since epoch) while waiting to retry
 };
 
 type StatusMap = Record<string, DownloadStatus>;
 
+export type DownloadLimits = {
+  max_concurrent: number;
+  global_bytes_per_sec?: number | null;
+  per_download_bytes_per_sec?: number | null;
+};
+
+// Terminal state of a download, kept by the backend for a while after it finishes
+export type FinishedDownload = {
+  key: string;
+  outcome: "completed" | "failed" | "cancelled";
+  error?: string | null; // reason when failed
+  bytes_downloaded: number;
+  total_bytes?: number | null;
+  attempts: number;
+  finished_at_ms: number;
+  warnings: string[]; // installed, but with these problems (missing cover...)
+};
+
+// Installed song with a newer version in its repository
+export type SongUpdate = {
+  id: string;
+  song_dirs: string[];
+  reason: string;
+};
+
+// Arguments shared by start_song_download and start_song_update
+const downloadArgs = (
+  key: string,
+  song: Song,
+  destRoot: string,
+  repository?: string,
+  safDest?: string,
+) => ({
+  key,
+  downloadUrl: song.downloadUrl,
+  destRoot,
+  safDest: safDest ?? null,
+  expectedSha256: song.downloadSha256 ?? null,
+  expectedSize: song.downloadSize ?? null,
+  // recorded in the song's parasync.json
+  repository: repository ?? null,
+  song: {
+    title: song.title,
+    artist: song.artist,
+    difficulties: song.difficulties,
+    uploadedBy: song.uploadedBy,
+    uploadedAt: song.uploadedAt,
+  },
+});
+
 // Extended type with song information for UI
 export type DownloadInfo = {
   status: DownloadStatus;
@@ -55,59 +117,146 @@ export class DownloadManager {
     return this._instance;
   }
 
-  private async start(key: string, song: Song, destRoot: string) {
+  private async start(
+    key: string,
+    song: Song,
+    destRoot: string,
+    repository?: string,
+    safDest?: string,
+  ) {
     // Store song information for this download
     this.activeSongs.set(key, song);
 
     // if it already exists in backend, it will return an error; we handle it above
-    await invoke("start_song_download", {
-      key,
-      downloadUrl: song.downloadUrl,
-      destRoot,
-    });
+    await invoke(
+      "start_song_download",
+      downloadArgs(key, song, destRoot, repository, safDest),
+    );
   }
 
-  async startAndWait(key: string, song: Song, _destRoot: string) {
+  async startAndWait(
+    key: string,
+    song: Song,
+    _destRoot: string,
+    repository?: string,
+  ) {
     // if not android download directly in the destRoot
-    // for android the download is made in the appDir/tmp folder and then copied with copyAppDirToSaf
+    // for android the download is made in the appDir/tmp folder and the backend copies it
+    // to the SAF songs root, also when the download is restored after a restart
     let destRoot = _destRoot;
-    let tmpUuid = uuid();
+    let safDest: string | undefined;
 
     if (IS_ANDROID) {
-      destRoot = await getAndroidTmpFolder(tmpUuid);
+      // one folder per song, so a failed download is continued when started again
+      destRoot = await getAndroidTmpFolder(
+        `download-${encodeURIComponent(key)}`,
+      );
+      safDest = "";
     }
 
     try {
-      await this.start(key, song, destRoot);
+      await this.start(key, song, destRoot, repository, safDest);
     } catch (error) {
       alert(
         "An error occurred while starting the download, please try again later.",
       );
     }
 
-    return new Promise<void>((resolve) => {
+    // resolves with the validation warnings of the installed song
+    return new Promise<string[]>((resolve, reject) => {
       const off = this.onStatus(async (statuses) => {
         if (!(key in statuses)) {
           // Clean up when download completes
           this.activeSongs.delete(key);
           off();
-          if (IS_ANDROID) {
-            try {
-              await SafManager.getInstance().copyAppDirToSaf(destRoot, "");
-              await removeAndroidTmpFolder(tmpUuid);
-            } catch (e) {
-              console.log(
-                "Error copying the downloaded song, check the app file permissions" +
-                  e,
-              );
+          const finished = (await this.history()).find((d) => d.key === key);
+          if (finished && finished.outcome !== "completed") {
+            if (finished.outcome === "failed") {
+              reject(new Error(finished.error ?? "Download failed"));
+            } else {
+              resolve([]);
             }
+            return;
           }
-          resolve();
+          resolve(finished?.warnings ?? []);
         }
       });
     });
   }
 
+  async cancel(key: string) {
+    await invoke("cancel_song_download", { key });
+  }
+
+  async pause(key: string) {
+    await invoke("pause_song_download", { key });
+  }
+
+  async resume(key: string) {
+    await invoke("resume_song_download", { key });
+  }
+
+  // Installed songs of destRoot whose repository version changed since they were installed
+  async checkUpdates(destRoot: string, songs: Song[]): Promise<SongUpdate[]> {
+    return await invoke("check_song_updates", {
+      destRoot,
+      songs: songs.map((s) => ({
+        id: s.id,
+        downloadSha256: s.downloadSha256 ?? null,
+        uploadedAt: s.uploadedAt,
+      })),
+    });
+  }
+
+  // Downloads the new version of an installed song and swaps it in; the saved mix is kept
+  async startUpdate(
+    key: string,
+    song: Song,
+    destRoot: string,
+    repository?: string,
+  ) {
+    this.activeSongs.set(key, song);
+    await invoke("start_song_update", downloadArgs(key, song, destRoot, repository));
+  }
+
+  // Imports a local archive into destRoot; returns the key to follow in the status
+  async importArchive(
+    path: string,
+    destRoot: string,
+    keepArchive = false,
+  ): Promise<string> {
+    return await invoke("import_song_archive", { path, destRoot, keepArchive });
+  }
+
+  // Imports an unpacked song folder (or a folder of songs) into destRoot
+  async importFolder(path: string, destRoot: string): Promise<string> {
+    return await invoke("import_song_folder", { path, destRoot });
+  }
+
+  // parasync.json of an installed song (provenance and repository metadata), null if none
+  async songManifest(songDir: string): Promise<SongManifest | null> {
+    return await invoke("song_manifest", { songDir });
+  }
+
+  // Concurrency and bandwidth caps (bytes per second, null = unlimited)
+  async getLimits(): Promise<DownloadLimits> {
+    return await invoke("download_limits");
+  }
+
+  async setLimits(limits: DownloadLimits) {
+    await invoke("set_download_limits", { limits });
+  }
+
+  // Recently finished downloads, newest first
+  async history(): Promise<FinishedDownload[]> {
+    return await invoke("downloads_history");
+  }
+
+  // Clears one entry of the history, or all of them without a key
+  async clearHistory(key?: string) {
+    await invoke("clear_downloads_history", { key: key ?? null });
+  }
+
   onStatus(cb: (statuses: StatusMap) => void) {
     this.listeners.add(cb);
     if (!this.timer) this.startPolling();
@@ -142,6 +291,12 @@ export class DownloadManager {
         total_bytes: null,
         progress: 0,
         extracting: false,
+        extraction: null,
+        paused: false,
+        attempt: 0,
+        validating: false,
+        warnings: [],
+        retry_at_ms: null,
       },
       song,
       startedAt: new Date(),
@@ -2,19 +2,28 @@ import { open } from "@tauri-apps/plugin-dialog";
 import {
   readDir,
   readTextFile,
-  readFile,
   remove,
-  exists,
-  mkdir,
 } from "@tauri-apps/plugin-fs";
 import { Difficulty, LocalSong, ParadiddleSong, Song } from "../types/songs";
 import { v4 as uuid } from "uuid";
 import * as path from "@tauri-apps/api/path";
 import { IS_ANDROID } from "./mobile";
 import { SafManager } from "./saf";
+import {
+  ARCHIVE_ENTRY_SEPARATOR,
+  SONG_ARCHIVE,
+  listSongArchive,
+  readSongFile,
+  splitArchivePath,
+} from "./archive";
 
 const PARASYNC_MANIFEST = "parasync.json";
 
+// Song folder (relative to the songs path) of a LocalSong.baseFileName.
+// The charts of a song kept compressed sit deeper, inside its archive.
+export const songFolderOf = (baseFileName: string): string =>
+  baseFileName.split("/")[0];
+
 export const selectSongsDirectory = async () => {
   const file = await open({
     multiple: false,
@@ -25,9 +34,7 @@ export const selectSongsDirectory = async () => {
 
 export const getImageUrl = async (imagePath: string): Promise<string> => {
   try {
-    const image = IS_ANDROID
-      ? await SafManager.getInstance().readFile(imagePath)
-      : await readFile(imagePath);
+    const image = await readSongFile(imagePath);
     const buf = (image as Uint8Array).buffer.slice(
       (image as Uint8Array).byteOffset,
       (image as Uint8Array).byteOffset + (image as Uint8Array).byteLength,
@@ -45,9 +52,7 @@ export const getParadiddleSong = async (
 ): Promise<ParadiddleSong> => {
   try {
     // Read as bytes first to detect encoding
-    const fileBytes = IS_ANDROID
-      ? await SafManager.getInstance().readFile(paradiddleSongPath)
-      : await readFile(paradiddleSongPath);
+    const fileBytes = await readSongFile(paradiddleSongPath);
 
     let jsonData: string;
 
@@ -110,7 +115,10 @@ export const getParadiddleSong = async (
       error,
     );
 
-    // Fallback: try with original readTextFile
+    // Fallback: try with original readTextFile (not for charts inside an archive)
+    if (splitArchivePath(paradiddleSongPath)) {
+      throw error;
+    }
     try {
       const jsonData = IS_ANDROID
         ? await SafManager.getInstance().readTextFile(paradiddleSongPath)
@@ -141,6 +149,9 @@ export const loadSong = async (
   let song: Song | null = null;
   let baseFileName = "";
   let manifestId: string | null = null;
+  let packId: string | undefined;
+  let chartNames: string[] = [];
+  let hasArchive = false;
 
   for (const entry of songDir) {
     if (entry.isFile) {
@@ -156,6 +167,9 @@ export const loadSong = async (
           if (manifest && typeof manifest.id === "string") {
             manifestId = manifest.id;
           }
+          if (manifest && typeof manifest.pack === "string") {
+            packId = manifest.pack;
+          }
         } catch (error) {
           console.error(
             `Error reading manifest for song folder ${songDirPath}:`,
@@ -165,49 +179,85 @@ export const loadSong = async (
         continue;
       }
 
-      if (!entry.name.endsWith(".rlrr")) {
-        continue;
+      if (entry.name === SONG_ARCHIVE) {
+        hasArchive = true;
       }
+      if (entry.name.endsWith(".rlrr")) {
+        chartNames.push(entry.name);
+      }
+    }
+  }
 
-      const baseName = entry.name.substring(0, entry.name.length - 5);
-      const lastUnderscoreIndex = baseName.lastIndexOf("_");
-      const resolvedBaseFileName =
-        lastUnderscoreIndex > -1
-          ? baseName.substring(0, lastUnderscoreIndex)
-          : baseName;
-
-      // if the song data is not loaded and it is a rlrr file read it
-      if (!song) {
-        try {
-          baseFileName = resolvedBaseFileName;
-          const paradiddleSong: ParadiddleSong = await getParadiddleSong(
-            `${songPath}/${entry.name}`,
-          );
-          song = {
-            title: paradiddleSong.recordingMetadata.title,
-            artist: paradiddleSong.recordingMetadata.artist,
-            id: uuid(),
-            difficulties: [],
-            uploadedAt: new Date().toISOString(),
-            uploadedBy: paradiddleSong.recordingMetadata.creator,
-            coverUrl: await getImageUrl(
-              `${songPath}/${paradiddleSong.recordingMetadata.coverImagePath}`,
-            ),
-          };
-        } catch (error) {
-          console.error(
-            `Error loading paradiddle song from ${entry.name}:`,
-            error,
-          );
-        }
+  // where the charts are: the song folder, or inside the archive of a song kept compressed
+  let chartDir = songDirPath;
+  if (chartNames.length === 0 && hasArchive) {
+    try {
+      // the charts sit at the root of the archive or in its top-level folder
+      const charts = (await listSongArchive(`${songPath}/${SONG_ARCHIVE}`))
+        .filter((e) => !e.is_dir && e.name.endsWith(".rlrr"))
+        .map((e) => e.name);
+      const depth = (name: string) => name.split("/").length;
+      const top = charts.reduce<string | null>(
+        (best, name) =>
+          best === null || depth(name) < depth(best) ? name : best,
+        null,
+      );
+      if (top !== null) {
+        const prefix = top.substring(0, top.lastIndexOf("/") + 1);
+        chartNames = charts
+          .filter((name) => name.startsWith(prefix))
+          .map((name) => name.substring(prefix.length))
+          .filter((name) => !name.includes("/"));
+        const archiveDir = `${songDirPath}/${SONG_ARCHIVE}${ARCHIVE_ENTRY_SEPARATOR}${prefix}`;
+        chartDir = archiveDir.replace(/\/$/, "");
       }
+    } catch (error) {
+      console.error(
+        `Error reading the archive of song folder ${songDirPath}:`,
+        error,
+      );
+    }
+  }
+
+  for (const chartName of chartNames) {
+    const baseName = chartName.substring(0, chartName.length - 5);
+    const lastUnderscoreIndex = baseName.lastIndexOf("_");
+    const resolvedBaseFileName =
+      lastUnderscoreIndex > -1
+        ? baseName.substring(0, lastUnderscoreIndex)
+        : baseName;
 
-      const difficulty =
-        lastUnderscoreIndex > -1
-          ? baseName.substring(lastUnderscoreIndex + 1)
-          : "Easy";
-      difficulties.push(difficulty as Difficulty);
+    // if the song data is not loaded and it is a rlrr file read it
+    if (!song) {
+      try {
+        baseFileName = resolvedBaseFileName;
+        const paradiddleSong: ParadiddleSong = await getParadiddleSong(
+          `${songsPath}/${chartDir}/${chartName}`,
+        );
+        song = {
+          title: paradiddleSong.recordingMetadata.title,
+          artist: paradiddleSong.recordingMetadata.artist,
+          id: uuid(),
+          difficulties: [],
+          uploadedAt: new Date().toISOString(),
+          uploadedBy: paradiddleSong.recordingMetadata.creator,
+          coverUrl: await getImageUrl(
+            `${songsPath}/${chartDir}/${paradiddleSong.recordingMetadata.coverImagePath}`,
+          ),
+        };
+      } catch (error) {
+        console.error(
+          `Error loading paradiddle song from ${chartName}:`,
+          error,
+        );
+      }
     }
+
+    const difficulty =
+      lastUnderscoreIndex > -1
+        ? baseName.substring(lastUnderscoreIndex + 1)
+        : "Easy";
+    difficulties.push(difficulty as Difficulty);
   }
 
   if (song) {
@@ -216,8 +266,9 @@ export const loadSong = async (
       song.id = manifestId;
     }
     return {
-      baseFileName: `${songDirPath}/${baseFileName}`,
+      baseFileName: `${chartDir}/${baseFileName}`,
       song,
+      packId,
     };
   } else {
     return null;
@@ -239,11 +290,7 @@ export const getLocalSongs = async (
   if (existingSongs) {
     existingSongs.forEach((song) => {
       // Extract just the folder part of the baseFileName for comparison
-      const folderName = song.baseFileName.substring(
-        0,
-        song.baseFileName.lastIndexOf("/"),
-      );
-      existingSongsMap.set(folderName, song);
+      existingSongsMap.set(songFolderOf(song.baseFileName), song);
     });
   }
 
@@ -282,49 +329,6 @@ export const removeAndroidTmpFolder = async (
   await remove(tmpDir, { recursive: true });
 };
 
-export const copyTracksToAndroidCache = async (
-  tracks: string[],
-  cacheKey: string,
-): Promise<string[]> => {
-  const cacheDir = await path.join(
-    await path.appLocalDataDir(),
-    "playerCache",
-    cacheKey,
-  );
-
-  if (!(await exists(cacheDir))) {
-    mkdir(cacheDir, { recursive: true });
-  }
-
-  const copyPromises = tracks.map(async (track) => {
-    const destPath = await path.join(cacheDir, track);
-    try {
-      await SafManager.getInstance().copySafToAppDir(
-        track,
-        destPath.substring(0, destPath.lastIndexOf("/") + 1),
-      );
-    } catch (e) {
-      console.log(e);
-    }
-    return destPath;
-  });
-
-  return Promise.all(copyPromises);
-};
-
-export const clearPlayerCache = async (cacheKey: string) => {
-  const cacheDir = await path.join(
-    await path.appLocalDataDir(),
-    "playerCache",
-    cacheKey,
-  );
-  try {
-    await remove(cacheDir, { recursive: true });
-  } catch (error) {
-    console.log(`Error clearing player cache for ${cacheKey}:` + error);
-  }
-};
-
 export const deleteSong = async (songsPath: string): Promise<void> => {
   // removes the song folder from the file system
   try {